@@ -0,0 +1,61 @@
+//! Compares the analyzer with the complex DFT it used to run on. Run with
+//! `cargo bench`.
+
+use std::f32::consts::PI;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[path = "../src/analyzer.rs"]
+#[allow(dead_code)]
+mod analyzer;
+
+use analyzer::{Analyzer, WindowFunction};
+
+/// The analysis as it was: windowing a copy of the samples, a complex DFT on
+/// them and a newly allocated spectrum of the full, mirrored length
+fn dft_transform(
+    plan: &dft::Plan<f32>,
+    window: &[f32],
+    samples: &[f32],
+    intensities: &mut Vec<f32>,
+) {
+    let mut dft_io_data = samples.to_vec();
+    dft_io_data.resize(window.len(), 0.0);
+    for (sample, weight) in dft_io_data.iter_mut().zip(window) {
+        *sample *= weight;
+    }
+    dft::transform(&mut dft_io_data, plan);
+
+    let scale_factor = 2.0 / window.iter().sum::<f32>();
+    *intensities = dft::unpack(&dft_io_data)
+        .iter()
+        .map(|c| (c.norm() * scale_factor).min(1.0))
+        .collect();
+}
+
+fn transform(c: &mut Criterion) {
+    let mut group = c.benchmark_group("transform");
+    for window_size in [1024, 4096] {
+        let samples: Vec<f32> = (0..window_size)
+            .map(|n| 0.5 * (2.0 * PI * 440.0 * n as f32 / 44100.0).sin())
+            .collect();
+        let mut intensities = vec![];
+
+        let plan = dft::Plan::<f32>::new(dft::Operation::Forward, window_size);
+        let window: Vec<f32> = (0..window_size)
+            .map(|n| 0.5 - 0.5 * (2.0 * PI * n as f32 / window_size as f32).cos())
+            .collect();
+        group.bench_function(BenchmarkId::new("dft", window_size), |b| {
+            b.iter(|| dft_transform(&plan, &window, black_box(&samples), &mut intensities))
+        });
+
+        let mut analyzer = Analyzer::new(window_size, WindowFunction::Hann);
+        group.bench_function(BenchmarkId::new("realfft", window_size), |b| {
+            b.iter(|| analyzer.transform(black_box(&samples), &mut intensities))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, transform);
+criterion_main!(benches);
@@ -0,0 +1,149 @@
+use std::sync::{Arc, Mutex};
+
+use alsa::pcm::{Access, Format, Frames, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::SampleRing;
+use crate::sampleconv::{self, PcmFormat};
+
+// Roughly 6 ms at 44.1 kHz, like PulseInput
+const DEFAULT_PERIOD_SIZE: usize = 256;
+const DEFAULT_PERIODS_PER_BUFFER: usize = 4;
+
+/// Records straight from an ALSA device, no sound server needed.
+/// Sizes are in frames.
+pub struct AlsaInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    pcm: PCM,
+    format: PcmFormat,
+    channels: usize,
+    bytes: Vec<u8>,
+    samples: Vec<f32>,
+}
+
+impl AlsaInput {
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        device: &str,
+        sample_rate: u32,
+        channels: u16,
+        period_size: Option<usize>,
+        buffer_size: Option<usize>,
+    ) -> Result<Self, String> {
+        let pcm = match PCM::new(device, Direction::Capture, false) {
+            Ok(pcm) => pcm,
+            Err(err) => return Err(format!("Cannot open ALSA device {device}: {err}")),
+        };
+
+        let period_size = period_size.unwrap_or(DEFAULT_PERIOD_SIZE);
+        let buffer_size = buffer_size.unwrap_or(period_size * DEFAULT_PERIODS_PER_BUFFER);
+        let (format, actual_rate, period_size) =
+            match configure(&pcm, sample_rate, channels, period_size, buffer_size) {
+                Ok(params) => params,
+                Err(err) => return Err(format!("Cannot configure ALSA device {device}: {err}")),
+            };
+        if actual_rate != sample_rate {
+            log::warn!("{device} does not support {sample_rate} Hz, using {actual_rate} Hz");
+        }
+
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = actual_rate;
+            playback_state.open_ring(channels as usize)
+        };
+
+        let channels = channels as usize;
+        Ok(AlsaInput {
+            playback_state,
+            ring,
+            pcm,
+            format,
+            channels,
+            bytes: vec![0; period_size * channels * format.sample_size()],
+            samples: Vec::with_capacity(period_size * channels),
+        })
+    }
+
+    /// Reads up to one period, recovering from overruns
+    fn read(&mut self) -> Result<(), String> {
+        let frame_count = match self.pcm.io_bytes().readi(&mut self.bytes) {
+            Ok(frame_count) => frame_count,
+            Err(err) => {
+                log::warn!("ALSA capture failed, recovering: {err}");
+                if let Err(err) = self.pcm.try_recover(err, true) {
+                    return Err(format!("Cannot recover ALSA capture: {err}"));
+                }
+                0
+            }
+        };
+
+        let byte_count = frame_count * self.channels * self.format.sample_size();
+        self.samples.clear();
+        self.format
+            .decode(&self.bytes[..byte_count], &mut self.samples);
+        Ok(())
+    }
+}
+
+/// Returns the sample format, rate and period size the device agreed to
+fn configure(
+    pcm: &PCM,
+    sample_rate: u32,
+    channels: u16,
+    period_size: usize,
+    buffer_size: usize,
+) -> alsa::Result<(PcmFormat, u32, usize)> {
+    let hwp = HwParams::any(pcm)?;
+    hwp.set_access(Access::RWInterleaved)?;
+    hwp.set_channels(channels as u32)?;
+    hwp.set_rate(sample_rate, ValueOr::Nearest)?;
+    // Most hardware only records integers
+    let format = if hwp.set_format(Format::FloatLE).is_ok() {
+        PcmFormat::F32le
+    } else {
+        hwp.set_format(Format::S16LE)?;
+        PcmFormat::S16le
+    };
+    let period_size = hwp.set_period_size_near(period_size as Frames, ValueOr::Nearest)?;
+    let buffer_size = hwp.set_buffer_size_near(buffer_size as Frames)?;
+    pcm.hw_params(&hwp)?;
+
+    log::info!(
+        "Recording {format:?}, {channels} channels, {period_size} frames per period, {buffer_size} frames buffered"
+    );
+    Ok((format, hwp.get_rate()?, period_size as usize))
+}
+
+impl AudioSource for AlsaInput {
+    fn run(&mut self) {
+        loop {
+            if let Err(err) = self.read() {
+                log::error!("{err}");
+                break;
+            }
+            self.ring
+                .push(&sampleconv::deinterleave(&self.samples, self.channels));
+
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs ALSA"]
+    fn records_from_null_device() {
+        let playback_state = Arc::new(Mutex::new(PlaybackState::new(1024)));
+        let mut input = AlsaInput::new(playback_state, "null", 44100, 2, Some(128), None).unwrap();
+        input.read().unwrap();
+        assert!(input.samples.iter().all(|sample| *sample == 0.0));
+    }
+}
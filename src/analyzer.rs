@@ -0,0 +1,149 @@
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use serde::Deserialize;
+
+/// Tapers the analysis window to reduce spectral leakage
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    BlackmanHarris,
+    /// Least accurate in frequency, most accurate in amplitude
+    FlatTop,
+}
+
+impl WindowFunction {
+    /// Coefficients of the cosine sum defining the window
+    fn cosine_terms(&self) -> &'static [f32] {
+        match self {
+            WindowFunction::Rectangular => &[1.0],
+            WindowFunction::Hann => &[0.5, 0.5],
+            WindowFunction::Hamming => &[0.54, 0.46],
+            WindowFunction::BlackmanHarris => &[0.35875, 0.48829, 0.14128, 0.01168],
+            WindowFunction::FlatTop => &[
+                0.215_578_95,
+                0.416_631_58,
+                0.277_263_16,
+                0.083_578_95,
+                0.006_947_37,
+            ],
+        }
+    }
+
+    /// Periodic window, as is usual for spectral analysis
+    fn coefficients(&self, window_size: usize) -> Vec<f32> {
+        (0..window_size)
+            .map(|n| {
+                let phase = 2.0 * PI * n as f32 / window_size as f32;
+                self.cosine_terms()
+                    .iter()
+                    .enumerate()
+                    .map(|(k, a)| {
+                        let sign = if k % 2 == 0 { 1.0 } else { -1.0 };
+                        sign * a * (k as f32 * phase).cos()
+                    })
+                    .sum()
+            })
+            .collect()
+    }
+}
+
+/// Turns a window of mono samples into a magnitude spectrum. All buffers are
+/// allocated up front, transforming doesn't allocate.
+pub struct Analyzer {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window_size: usize,
+    window: Vec<f32>,
+    scale_factor: f32,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Analyzer {
+    pub fn new(window_size: usize, window_function: WindowFunction) -> Analyzer {
+        let window = window_function.coefficients(window_size);
+        // A sine with amplitude 1 centered on a bin shows up with magnitude
+        // sum(window) / 2, scale that to 1 regardless of window size and shape
+        let scale_factor = 2.0 / window.iter().sum::<f32>();
+
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(window_size);
+        Analyzer {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            window_size,
+            window,
+            scale_factor,
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    /// Number of bins in a spectrum, from DC up to and including Nyquist
+    pub fn bin_count(&self) -> usize {
+        self.window_size / 2 + 1
+    }
+
+    /// Missing samples at the end of a short window count as silence
+    pub fn transform(&mut self, samples: &[f32], intensities: &mut Vec<f32>) {
+        let samples = &samples[..samples.len().min(self.window_size)];
+        for ((input, sample), weight) in self.input.iter_mut().zip(samples).zip(&self.window) {
+            *input = sample * weight;
+        }
+        self.input[samples.len()..].fill(0.0);
+        // Only fails for buffers of the wrong length
+        self.fft
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .unwrap();
+
+        intensities.resize(self.output.len(), 0.0);
+        for (intensity, c) in intensities.iter_mut().zip(&self.output) {
+            *intensity = (c.norm() * self.scale_factor).min(1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(window_size: usize, bin: f32, amplitude: f32) -> Vec<f32> {
+        (0..window_size)
+            .map(|n| amplitude * (2.0 * PI * bin * n as f32 / window_size as f32).sin())
+            .collect()
+    }
+
+    #[test]
+    fn magnitudes_match_amplitude() {
+        let windows = [
+            WindowFunction::Rectangular,
+            WindowFunction::Hann,
+            WindowFunction::Hamming,
+            WindowFunction::BlackmanHarris,
+            WindowFunction::FlatTop,
+        ];
+        let mut intensities = vec![];
+        for window_size in [512, 2048] {
+            for window in windows {
+                let mut analyzer = Analyzer::new(window_size, window);
+                analyzer.transform(&sine(window_size, 20.0, 0.5), &mut intensities);
+                assert_eq!(intensities.len(), analyzer.bin_count());
+                assert!((intensities[20] - 0.5).abs() < 0.005, "{window:?}");
+            }
+        }
+
+        // Flat top keeps the amplitude even between two bins
+        let mut analyzer = Analyzer::new(1024, WindowFunction::FlatTop);
+        analyzer.transform(&sine(1024, 20.5, 0.5), &mut intensities);
+        assert!((intensities[20] - 0.5).abs() < 0.005);
+    }
+}
@@ -0,0 +1,35 @@
+use std::fs;
+
+use crate::compresseddecoder;
+use crate::wavdecoder;
+
+/// Decoded audio with interleaved f32 samples in [-1; 1]
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+pub fn load(file_path: &str) -> Result<DecodedAudio, String> {
+    let bytes = match fs::read(file_path) {
+        Ok(bytes) => bytes,
+        Err(err) => return Err(format!("Cannot read {file_path}: {err}")),
+    };
+
+    // Our own decoder keeps WAV playback bit-exact, everything else goes
+    // through symphonia.
+    let result = if is_wav(&bytes) {
+        wavdecoder::decode_wav(&bytes)
+    } else {
+        compresseddecoder::decode_compressed(bytes)
+    };
+
+    match result {
+        Ok(audio) => Ok(audio),
+        Err(msg) => Err(format!("Cannot decode {file_path}: {msg}")),
+    }
+}
+
+fn is_wav(bytes: &[u8]) -> bool {
+    bytes.len() >= 12 && &bytes[0..4] == b"RIFF" && &bytes[8..12] == b"WAVE"
+}
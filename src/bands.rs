@@ -0,0 +1,287 @@
+use std::sync::Arc;
+
+use serde::Deserialize;
+
+// The effects used to react to DFT bin 2 of 1024 at 44.1 kHz
+const DEFAULT_EFFECT_FREQ: f32 = 86.0;
+
+fn default_min_freq() -> f32 {
+    30.0
+}
+
+fn default_max_freq() -> f32 {
+    16000.0
+}
+
+/// How to divide the spectrum into bands
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "layout", rename_all = "kebab-case")]
+pub enum BandConfig {
+    /// `count` bands evenly spaced on a logarithmic scale
+    Log {
+        count: usize,
+        #[serde(default = "default_min_freq")]
+        min_freq: f32,
+        #[serde(default = "default_max_freq")]
+        max_freq: f32,
+    },
+    /// Standard octave bands centered between `min_freq` and `max_freq`
+    Octave {
+        #[serde(default = "default_min_freq")]
+        min_freq: f32,
+        #[serde(default = "default_max_freq")]
+        max_freq: f32,
+    },
+    /// Standard third-octave bands centered between `min_freq` and `max_freq`
+    ThirdOctave {
+        #[serde(default = "default_min_freq")]
+        min_freq: f32,
+        #[serde(default = "default_max_freq")]
+        max_freq: f32,
+    },
+    Explicit {
+        bands: Vec<Band>,
+    },
+}
+
+impl Default for BandConfig {
+    /// Twelve bands, as many as the OSC graph shows
+    fn default() -> Self {
+        BandConfig::Log {
+            count: 12,
+            min_freq: default_min_freq(),
+            max_freq: default_max_freq(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct Band {
+    pub name: String,
+    /// In Hz
+    pub low: f32,
+    /// In Hz
+    pub high: f32,
+}
+
+impl Band {
+    fn from_range(low: f32, high: f32) -> Band {
+        let center = (low * high).sqrt();
+        Band {
+            name: format!("{center:.0} Hz"),
+            low,
+            high,
+        }
+    }
+
+    fn center(&self) -> f32 {
+        (self.low * self.high).sqrt()
+    }
+}
+
+pub struct BandLayout {
+    bands: Vec<Band>,
+    /// Index of the band the effects react to
+    effect_band: usize,
+}
+
+impl BandLayout {
+    /// `effect_band` is a band name or index, it defaults to the bass band
+    pub fn new(config: &BandConfig, effect_band: Option<&str>) -> Result<BandLayout, String> {
+        let bands = match config {
+            BandConfig::Log {
+                count,
+                min_freq,
+                max_freq,
+            } => {
+                let ratio = max_freq / min_freq;
+                let edge = |i: usize| min_freq * ratio.powf(i as f32 / *count as f32);
+                (0..*count)
+                    .map(|i| Band::from_range(edge(i), edge(i + 1)))
+                    .collect()
+            }
+            BandConfig::Octave { min_freq, max_freq } => {
+                fractional_octaves(1.0, *min_freq, *max_freq)
+            }
+            BandConfig::ThirdOctave { min_freq, max_freq } => {
+                fractional_octaves(3.0, *min_freq, *max_freq)
+            }
+            BandConfig::Explicit { bands } => bands.clone(),
+        };
+
+        if bands.is_empty() {
+            return Err("Band layout has no bands".to_string());
+        }
+        if let Some(band) = bands
+            .iter()
+            .find(|band| band.low <= 0.0 || band.low >= band.high)
+        {
+            return Err(format!(
+                "Band \"{}\" must span a positive frequency range",
+                band.name
+            ));
+        }
+
+        let effect_band = match effect_band {
+            Some(name) => match bands.iter().position(|band| band.name == name) {
+                Some(index) => index,
+                None => match name.parse::<usize>() {
+                    Ok(index) if index < bands.len() => index,
+                    _ => return Err(format!("Unknown effect band \"{name}\"")),
+                },
+            },
+            None => nearest_band(&bands, DEFAULT_EFFECT_FREQ),
+        };
+
+        Ok(BandLayout { bands, effect_band })
+    }
+
+    pub fn bands(&self) -> &[Band] {
+        &self.bands
+    }
+
+    pub fn effect_band(&self) -> usize {
+        self.effect_band
+    }
+
+    /// The level of each band is the loudest bin within it. Bands narrower
+    /// than a bin use the bin nearest to their center.
+    fn aggregate(&self, spectrum: &[f32], freq_step: f32, levels: &mut Vec<f32>) {
+        levels.clear();
+        if spectrum.is_empty() {
+            levels.resize(self.bands.len(), 0.0);
+            return;
+        }
+
+        let last_bin = spectrum.len() - 1;
+        for band in &self.bands {
+            let first = ((band.low / freq_step).ceil() as usize).min(last_bin);
+            let last = ((band.high / freq_step).ceil() as usize).min(spectrum.len());
+            let level = if first < last {
+                spectrum[first..last].iter().fold(0.0f32, |a, b| a.max(*b))
+            } else {
+                spectrum[((band.center() / freq_step).round() as usize).min(last_bin)]
+            };
+            levels.push(level);
+        }
+    }
+}
+
+impl Default for BandLayout {
+    fn default() -> Self {
+        BandLayout::new(&BandConfig::default(), None).unwrap()
+    }
+}
+
+/// Bands around the base-two center frequencies relative to 1 kHz
+fn fractional_octaves(fraction: f32, min_freq: f32, max_freq: f32) -> Vec<Band> {
+    let half_width = 2.0f32.powf(0.5 / fraction);
+    let first = (fraction * (min_freq / 1000.0).log2()).ceil() as i32;
+    let last = (fraction * (max_freq / 1000.0).log2()).floor() as i32;
+    (first..=last)
+        .map(|i| {
+            let center = 1000.0 * 2.0f32.powf(i as f32 / fraction);
+            Band::from_range(center / half_width, center * half_width)
+        })
+        .collect()
+}
+
+fn nearest_band(bands: &[Band], freq: f32) -> usize {
+    let distance = |band: &Band| (band.center().ln() - freq.ln()).abs();
+    (0..bands.len())
+        .min_by(|a, b| distance(&bands[*a]).total_cmp(&distance(&bands[*b])))
+        .unwrap_or(0)
+}
+
+/// Levels of all bands of one spectrum
+#[derive(Clone)]
+pub struct Bands {
+    layout: Arc<BandLayout>,
+    levels: Vec<f32>,
+}
+
+impl Bands {
+    pub fn new(layout: Arc<BandLayout>) -> Bands {
+        Bands {
+            levels: vec![0.0; layout.bands.len()],
+            layout,
+        }
+    }
+
+    pub fn update(&mut self, spectrum: &[f32], freq_step: f32) {
+        self.layout.aggregate(spectrum, freq_step, &mut self.levels);
+    }
+
+    pub fn layout(&self) -> &Arc<BandLayout> {
+        &self.layout
+    }
+
+    pub fn levels(&self) -> &[f32] {
+        &self.levels
+    }
+
+    pub fn levels_mut(&mut self) -> &mut [f32] {
+        &mut self.levels
+    }
+
+    /// Level of the band the effects react to
+    pub fn effect_level(&self) -> f32 {
+        self.levels[self.layout.effect_band]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_standard_layouts() {
+        let octaves = BandLayout::new(
+            &BandConfig::Octave {
+                min_freq: 30.0,
+                max_freq: 16000.0,
+            },
+            None,
+        )
+        .unwrap();
+        assert_eq!(octaves.bands().len(), 10);
+        assert_eq!(octaves.bands()[5].name, "1000 Hz");
+        assert_eq!(octaves.effect_band, 1);
+
+        let thirds = BandLayout::new(
+            &BandConfig::ThirdOctave {
+                min_freq: 1000.0,
+                max_freq: 2000.0,
+            },
+            Some("1260 Hz"),
+        )
+        .unwrap();
+        assert_eq!(thirds.bands().len(), 4);
+        assert_eq!(thirds.effect_band, 1);
+    }
+
+    #[test]
+    fn aggregates_bins_into_bands() {
+        let config = BandConfig::Explicit {
+            bands: vec![
+                Band {
+                    name: "bass".to_string(),
+                    low: 60.0,
+                    high: 250.0,
+                },
+                Band {
+                    name: "narrow".to_string(),
+                    low: 420.0,
+                    high: 430.0,
+                },
+            ],
+        };
+        let layout = Arc::new(BandLayout::new(&config, Some("bass")).unwrap());
+        let mut bands = Bands::new(layout);
+
+        // Bins are 100 Hz apart
+        bands.update(&[0.9, 0.1, 0.5, 0.3, 0.7, 0.2], 100.0);
+        assert_eq!(bands.levels(), &[0.5, 0.7]);
+        assert_eq!(bands.effect_level(), 0.5);
+    }
+}
@@ -0,0 +1,148 @@
+use std::io::Cursor;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{Decoder, DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error;
+use symphonia::core::formats::{FormatOptions, FormatReader};
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::audiofile::DecodedAudio;
+
+/// Picks the first audio track and creates its decoder
+fn open_track(format: &dyn FormatReader) -> Result<(u32, Box<dyn Decoder>), String> {
+    let track = match format
+        .tracks()
+        .iter()
+        .find(|track| track.codec_params.codec != CODEC_TYPE_NULL)
+    {
+        Some(track) => track,
+        None => return Err("No audio track found".to_string()),
+    };
+    match symphonia::default::get_codecs().make(&track.codec_params, &DecoderOptions::default()) {
+        Ok(decoder) => Ok((track.id, decoder)),
+        Err(err) => Err(format!("Unsupported codec: {err}")),
+    }
+}
+
+/// Decodes FLAC, Ogg Vorbis and MP3 files. The container format is probed
+/// from the file contents, not from the file name.
+pub fn decode_compressed(bytes: Vec<u8>) -> Result<DecodedAudio, String> {
+    let stream = MediaSourceStream::new(Box::new(Cursor::new(bytes)), Default::default());
+    let probed = match symphonia::default::get_probe().format(
+        &Hint::new(),
+        stream,
+        &FormatOptions::default(),
+        &MetadataOptions::default(),
+    ) {
+        Ok(probed) => probed,
+        Err(err) => return Err(format!("Unsupported file format: {err}")),
+    };
+    let mut format = probed.format;
+
+    let (mut track_id, mut decoder) = open_track(format.as_ref())?;
+
+    let mut sample_rate = decoder.codec_params().sample_rate.unwrap_or(0);
+    let mut channels = match decoder.codec_params().channels {
+        Some(channels) => channels.count() as u16,
+        None => 0,
+    };
+    let mut samples = Vec::new();
+    let mut sample_buffer: Option<SampleBuffer<f32>> = None;
+
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(Error::IoError(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            // The next stream of a chained Ogg file begins, with new tracks
+            Err(Error::ResetRequired) => {
+                (track_id, decoder) = open_track(format.as_ref())?;
+                sample_buffer = None;
+                continue;
+            }
+            Err(err) => return Err(format!("Cannot read packet: {err}")),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+
+        match decoder.decode(&packet) {
+            Ok(decoded) => {
+                let spec = *decoded.spec();
+                // All samples end up in one buffer, which has a single format
+                if !samples.is_empty()
+                    && (spec.rate != sample_rate || spec.channels.count() as u16 != channels)
+                {
+                    return Err(
+                        "Chained streams with different sample rates or channels are not supported"
+                            .to_string(),
+                    );
+                }
+                sample_rate = spec.rate;
+                channels = spec.channels.count() as u16;
+
+                let buffer = sample_buffer.get_or_insert_with(|| {
+                    SampleBuffer::<f32>::new(decoded.capacity() as u64, spec)
+                });
+                if buffer.capacity() < decoded.capacity() * spec.channels.count() {
+                    *buffer = SampleBuffer::<f32>::new(decoded.capacity() as u64, spec);
+                }
+                buffer.copy_interleaved_ref(decoded);
+                samples.extend_from_slice(buffer.samples());
+            }
+            // Corrupt frames are skipped, the decoder recovers on the next packet
+            Err(Error::DecodeError(msg)) => log::warn!("Skipping undecodable packet: {msg}"),
+            Err(err) => return Err(format!("Cannot decode packet: {err}")),
+        }
+    }
+
+    if sample_rate == 0 || channels == 0 {
+        return Err("File contains no decodable audio".to_string());
+    }
+
+    Ok(DecodedAudio {
+        sample_rate,
+        channels,
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fixtures hold a 441 Hz sine at half amplitude, 16 bit mono. The
+    /// second stream of the chained file plays an octave higher.
+    fn assert_sine(samples: &[f32], frames: usize) {
+        for (i, sample) in samples.iter().take(frames).enumerate() {
+            let expected = 0.5 * (2.0 * std::f32::consts::PI * 441.0 * i as f32 / 44100.0).sin();
+            assert!((sample - expected).abs() < 1e-3, "sample {i}: {sample}");
+        }
+    }
+
+    #[test]
+    fn decodes_flac() {
+        let flac = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/sine.flac"));
+        let audio = decode_compressed(flac.to_vec()).unwrap();
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.channels, 1);
+        assert_eq!(audio.samples.len(), 1024);
+        assert_sine(&audio.samples, 1024);
+    }
+
+    #[test]
+    fn decodes_every_stream_of_chained_ogg() {
+        let ogg = include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/chained.ogg"));
+        let audio = decode_compressed(ogg.to_vec()).unwrap();
+        assert_eq!(audio.sample_rate, 44100);
+        assert_eq!(audio.samples.len(), 1024);
+        assert_sine(&audio.samples, 512);
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert!(decode_compressed(vec![]).is_err());
+        assert!(decode_compressed((0..4096).map(|i| (i * 7 % 251) as u8).collect()).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::{Arc, Mutex};
+
+use palette::blend::Blend;
+use palette::WithAlpha;
+
+use crate::effects::{self, LightingEffect};
+use crate::features::AudioFeatures;
+use crate::PhotonizerOptions;
+
+/// Like the light bar, but pans across the strip by stereo balance: the left
+/// channel's energy shows on the left end, the right channel's on the right.
+pub struct Balance {
+    options: Arc<Mutex<PhotonizerOptions>>,
+    pixel_count: usize,
+    peak_falloff: f32,
+    last_peaks: [f32; 2],
+}
+
+impl Balance {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+    ) -> Balance {
+        Balance {
+            options,
+            pixel_count,
+            peak_falloff: effects::peak_falloff(frame_rate),
+            last_peaks: [0.0; 2],
+        }
+    }
+}
+
+impl LightingEffect for Balance {
+    fn step(&mut self, features: &AudioFeatures) -> Vec<palette::LinSrgb> {
+        // Mono input lights up the whole strip evenly
+        let (left, right) = match features.channel_bands.as_slice() {
+            [left, right, ..] => (left, right),
+            _ => (&features.bands, &features.bands),
+        };
+        for (peak, channel) in self.last_peaks.iter_mut().zip([left, right]) {
+            let cur_val = channel.effect_level().clamp(0.0, 1.0);
+            if cur_val > *peak {
+                *peak = cur_val;
+            }
+        }
+
+        let black = palette::LinSrgba::new(0.0, 0.0, 0.0, 1.0);
+        let accent_color = self.options.lock().unwrap().accent_color;
+        let last_pixel = (self.pixel_count - 1).max(1) as f32;
+        let frame = (0..self.pixel_count)
+            .map(|i| {
+                let pan = i as f32 / last_pixel;
+                let level = self.last_peaks[0] * (1.0 - pan) + self.last_peaks[1] * pan;
+                black.overlay(accent_color.with_alpha(level)).color
+            })
+            .collect();
+
+        for peak in &mut self.last_peaks {
+            *peak *= self.peak_falloff;
+        }
+        frame
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bands::BandLayout;
+    use crate::photonizer::DEFAULT_FRAME_RATE;
+
+    #[test]
+    fn pans_by_channel_energy() {
+        let options = Arc::new(Mutex::new(PhotonizerOptions::new()));
+        let mut balance = Balance::new(options, 5, DEFAULT_FRAME_RATE);
+        let mut features = AudioFeatures::new(Arc::new(BandLayout::default()));
+        let loud = vec![1.0; 513];
+        features.update_spectra(&loud, &[loud.clone(), vec![0.0; 513]], 43.0);
+
+        let frame = balance.step(&features);
+        let greens: Vec<f32> = frame.iter().map(|c| c.green).collect();
+        assert_eq!(greens[0], 1.0);
+        assert_eq!(greens[2], 0.5);
+        assert_eq!(greens[4], 0.0);
+    }
+}
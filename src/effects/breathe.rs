@@ -0,0 +1,45 @@
+use std::f32::consts::PI;
+use std::sync::{Arc, Mutex};
+
+use crate::effects::LightingEffect;
+use crate::features::AudioFeatures;
+use crate::photonizer::PhotonizerOptions;
+
+/// Seconds for one breath
+const PERIOD: f32 = 6.0;
+const MIN_INTENSITY: f32 = 0.1;
+
+/// Fades the accent color in and out, ignoring the audio
+pub struct Breathe {
+    options: Arc<Mutex<PhotonizerOptions>>,
+    pixel_count: usize,
+    frame_rate: f32,
+    time: f32,
+}
+
+impl Breathe {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+    ) -> Breathe {
+        Breathe {
+            options,
+            pixel_count,
+            frame_rate,
+            time: 0.0,
+        }
+    }
+}
+
+impl LightingEffect for Breathe {
+    fn step(&mut self, _: &AudioFeatures) -> Vec<palette::LinSrgb> {
+        // Starts out dark, coming from whatever the previous effect showed
+        let breath = 0.5 - 0.5 * (2.0 * PI * self.time / PERIOD).cos();
+        let intensity = MIN_INTENSITY + (1.0 - MIN_INTENSITY) * breath;
+        self.time = (self.time + 1.0 / self.frame_rate) % PERIOD;
+
+        let color = self.options.lock().unwrap().accent_color;
+        vec![color * intensity; self.pixel_count]
+    }
+}
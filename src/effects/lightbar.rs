@@ -3,7 +3,8 @@ use std::sync::{Arc, Mutex};
 use palette::blend::Blend;
 use palette::WithAlpha;
 
-use crate::effects::LightingEffect;
+use crate::effects::{self, LightingEffect};
+use crate::features::AudioFeatures;
 use crate::PhotonizerOptions;
 
 pub struct LightBar {
@@ -14,19 +15,23 @@ pub struct LightBar {
 }
 
 impl LightBar {
-    pub fn new(options: Arc<Mutex<PhotonizerOptions>>, pixel_count: usize) -> LightBar {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+    ) -> LightBar {
         LightBar {
             options,
             pixel_count,
-            peak_falloff: 0.9,
+            peak_falloff: effects::peak_falloff(frame_rate),
             last_peak: 0.0,
         }
     }
 }
 
 impl LightingEffect for LightBar {
-    fn step(&mut self, intensities: &Vec<f32>) -> Vec<palette::LinSrgb> {
-        let cur_val = intensities[2].clamp(0.0, 1.0);
+    fn step(&mut self, features: &AudioFeatures) -> Vec<palette::LinSrgb> {
+        let cur_val = features.bands.effect_level().clamp(0.0, 1.0);
         if cur_val > self.last_peak {
             self.last_peak = cur_val;
         }
@@ -1,10 +1,32 @@
+pub(crate) mod balance;
+pub(crate) mod breathe;
 pub(crate) mod lightbar;
 pub(crate) mod pixelflow;
 pub(crate) mod staticcolor;
 pub(crate) mod thunderstruck;
 
+use std::sync::{Arc, Mutex};
+
+use crate::features::AudioFeatures;
+use crate::photonizer::{Mode, PhotonizerOptions, DEFAULT_FRAME_RATE};
+use balance::Balance;
+use lightbar::LightBar;
+use pixelflow::PixelFlow;
+use staticcolor::StaticColor;
+use thunderstruck::Thunderstruck;
+
+/// Effects are created for a frame rate, they expect `step` to be called
+/// that many times per second
 pub trait LightingEffect {
-    fn step(&mut self, intensities: &Vec<f32>) -> Vec<palette::LinSrgb>;
+    fn step(&mut self, features: &AudioFeatures) -> Vec<palette::LinSrgb>;
+}
+
+/// Per frame decay of peaks and pulses at the default frame rate
+const PEAK_FALLOFF: f32 = 0.9;
+
+/// `PEAK_FALLOFF` for frames at `frame_rate`, so peaks fade equally fast
+fn peak_falloff(frame_rate: f32) -> f32 {
+    PEAK_FALLOFF.powf(DEFAULT_FRAME_RATE / frame_rate)
 }
 
 struct Pulse {
@@ -12,3 +34,30 @@ struct Pulse {
     intensity: f32,
     position: f32,
 }
+
+pub fn create_effect(
+    mode: Mode,
+    options: Arc<Mutex<PhotonizerOptions>>,
+    pixel_count: usize,
+    frame_rate: f32,
+) -> Box<dyn LightingEffect + Send> {
+    match mode {
+        Mode::LightBar => Box::new(LightBar::new(options, pixel_count, frame_rate)),
+        Mode::Pixels => Box::new(PixelFlow::new(options, pixel_count, frame_rate)),
+        Mode::Static => Box::new(StaticColor::new(options, pixel_count)),
+        Mode::Thunderstruck => Box::new(Thunderstruck::new(options, pixel_count, frame_rate)),
+        Mode::Balance => Box::new(Balance::new(options, pixel_count, frame_rate)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peaks_fade_equally_fast_at_any_frame_rate() {
+        let second_at_default = PEAK_FALLOFF.powf(DEFAULT_FRAME_RATE);
+        let second_at_60 = peak_falloff(60.0).powf(60.0);
+        assert!((second_at_default - second_at_60).abs() < 1e-6);
+    }
+}
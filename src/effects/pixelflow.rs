@@ -5,29 +5,34 @@ use palette::WithAlpha;
 
 use crate::effects::LightingEffect;
 use crate::effects::Pulse;
+use crate::features::AudioFeatures;
+use crate::photonizer::DEFAULT_FRAME_RATE;
 use crate::PhotonizerOptions;
 
 pub struct PixelFlow {
     options: Arc<Mutex<PhotonizerOptions>>,
     pixel_count: usize,
-    peak_falloff: f32,
-    last_peak: f32,
+    /// Frames at the default frame rate per actual frame
+    speed_scale: f32,
     pulses: Vec<Pulse>,
 }
 
 impl PixelFlow {
-    pub fn new(options: Arc<Mutex<PhotonizerOptions>>, pixel_count: usize) -> PixelFlow {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+    ) -> PixelFlow {
         PixelFlow {
             options,
             pixel_count,
-            peak_falloff: 0.95,
-            last_peak: 0.0,
+            speed_scale: DEFAULT_FRAME_RATE / frame_rate,
             pulses: vec![],
         }
     }
 
     fn advance_pulses(&mut self) {
-        let pulse_speed = self.options.lock().unwrap().pulse_speed;
+        let pulse_speed = self.options.lock().unwrap().pulse_speed * self.speed_scale;
 
         for pulse in &mut self.pulses {
             pulse.position += pulse_speed;
@@ -54,33 +59,29 @@ impl PixelFlow {
         }
     }
 
-    fn create_pulse(&mut self, intensities: &Vec<f32>) {
+    fn create_pulse(&mut self, features: &AudioFeatures) {
         let accent_color = self.options.lock().unwrap().accent_color;
-        let cur_val = intensities[2].clamp(0.0, 1.0);
-        if cur_val > self.last_peak {
+        if features.beat {
             if let Some(last_pulse) = self.pulses.last() {
                 if last_pulse.position < 1.0 {
                     return;
                 }
             }
 
-            self.last_peak = cur_val;
             self.pulses.push(Pulse {
                 color: accent_color,
                 position: 0.0,
                 intensity: 1.0,
             });
         }
-
-        self.last_peak *= self.peak_falloff;
     }
 }
 
 impl LightingEffect for PixelFlow {
-    fn step(&mut self, intensities: &Vec<f32>) -> Vec<palette::LinSrgb> {
+    fn step(&mut self, features: &AudioFeatures) -> Vec<palette::LinSrgb> {
         self.advance_pulses();
         self.remove_pulses();
-        self.create_pulse(intensities);
+        self.create_pulse(features);
 
         let black = palette::LinSrgba::new(0.0, 0.0, 0.0, 1.0);
         let mut frame_buffer = vec![black; self.pixel_count];
@@ -1,6 +1,7 @@
 use std::sync::{Arc, Mutex};
 
 use crate::effects::LightingEffect;
+use crate::features::AudioFeatures;
 use crate::PhotonizerOptions;
 
 pub struct StaticColor {
@@ -18,7 +19,7 @@ impl StaticColor {
 }
 
 impl LightingEffect for StaticColor {
-    fn step(&mut self, _: &Vec<f32>) -> Vec<palette::LinSrgb> {
+    fn step(&mut self, _: &AudioFeatures) -> Vec<palette::LinSrgb> {
         let color = self.options.lock().unwrap().accent_color;
         vec![color; self.pixel_count]
     }
@@ -2,28 +2,57 @@ use std::sync::{Arc, Mutex};
 
 use palette::blend::Blend;
 use palette::WithAlpha;
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
-use crate::effects::LightingEffect;
-use crate::effects::Pulse;
+use crate::effects::{self, LightingEffect, Pulse};
+use crate::features::AudioFeatures;
 use crate::PhotonizerOptions;
 
 pub struct Thunderstruck {
     options: Arc<Mutex<PhotonizerOptions>>,
     pixel_count: usize,
     peak_falloff: f32,
-    last_peak: f32,
     pulses: Vec<Pulse>,
+    rng: StdRng,
 }
 
 impl Thunderstruck {
-    pub fn new(options: Arc<Mutex<PhotonizerOptions>>, pixel_count: usize) -> Thunderstruck {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+    ) -> Thunderstruck {
+        Thunderstruck::with_rng(options, pixel_count, frame_rate, StdRng::from_entropy())
+    }
+
+    /// Strikes land on the same pixels on every run, for reproducible renders.
+    pub fn with_seed(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+        seed: u64,
+    ) -> Thunderstruck {
+        Thunderstruck::with_rng(
+            options,
+            pixel_count,
+            frame_rate,
+            StdRng::seed_from_u64(seed),
+        )
+    }
+
+    fn with_rng(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        pixel_count: usize,
+        frame_rate: f32,
+        rng: StdRng,
+    ) -> Thunderstruck {
         Thunderstruck {
             options,
             pixel_count,
-            peak_falloff: 0.9,
-            last_peak: 0.0,
+            peak_falloff: effects::peak_falloff(frame_rate),
             pulses: vec![],
+            rng,
         }
     }
 
@@ -37,29 +66,27 @@ impl Thunderstruck {
         self.pulses.retain(|pulse| pulse.intensity > 0.1);
     }
 
-    fn create_strike(&mut self, intensities: &Vec<f32>) {
-        let cur_val = intensities[2].clamp(0.0, 1.0);
-        if cur_val < self.last_peak {
-            return;
-        }
+    fn create_strike(&mut self, features: &AudioFeatures) {
+        let onset = match features.onset {
+            Some(onset) => onset,
+            None => return,
+        };
 
-        self.last_peak = cur_val;
+        // Stronger onsets strike brighter
         let white = palette::LinSrgb::new(1.0, 1.0, 1.0);
         self.pulses.push(Pulse {
             color: white,
-            position: rand::thread_rng().gen_range(0..self.pixel_count) as f32,
-            intensity: 1.0,
+            position: self.rng.gen_range(0..self.pixel_count) as f32,
+            intensity: 0.5 + 0.5 * onset.strength,
         });
-
-        self.last_peak *= self.peak_falloff;
     }
 }
 
 impl LightingEffect for Thunderstruck {
-    fn step(&mut self, intensities: &Vec<f32>) -> Vec<palette::LinSrgb> {
+    fn step(&mut self, features: &AudioFeatures) -> Vec<palette::LinSrgb> {
         self.decay_strikes();
         self.remove_strikes();
-        self.create_strike(intensities);
+        self.create_strike(features);
 
         let black = palette::LinSrgba::new(0.0, 0.0, 0.0, 1.0);
         let accent_color = self.options.lock().unwrap().accent_color.with_alpha(0.3);
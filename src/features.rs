@@ -0,0 +1,215 @@
+use std::sync::Arc;
+
+use crate::bands::{BandLayout, Bands};
+use crate::onsets::Beat;
+use crate::tempo::Tempo;
+
+const BASS_RANGE: (f32, f32) = (20.0, 250.0);
+const MID_RANGE: (f32, f32) = (250.0, 4000.0);
+const TREBLE_RANGE: (f32, f32) = (4000.0, 20000.0);
+
+/// Everything the analysis found out about the audio of one frame
+#[derive(Clone)]
+pub struct AudioFeatures {
+    /// Of the mid signal's samples since the last frame
+    pub rms: f32,
+    pub peak: f32,
+
+    /// Levels of the configured bands
+    pub bands: Bands,
+    /// Levels of the configured bands per input channel
+    pub channel_bands: Vec<Bands>,
+    /// Root of the summed squared magnitudes in the range, about the amplitude
+    /// of the loudest tones there
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+
+    /// Center of mass of the spectrum in Hz, bright sounds have a high one
+    pub centroid: f32,
+    /// In [0; 1], 1 for noise and close to 0 for pure tones
+    pub flatness: f32,
+    /// Onset strength, the rise in magnitude since the last spectrum
+    pub flux: f32,
+
+    /// The onset detected since the last frame, if any
+    pub onset: Option<Beat>,
+    pub tempo: Option<Tempo>,
+    /// Set on the frame a beat starts: on every beat of the tempo while there
+    /// is one, otherwise on every onset
+    pub beat: bool,
+
+    /// Magnitude spectrum of the mid signal, from DC up to Nyquist
+    pub spectrum: Vec<f32>,
+    /// Bandwidth of each bin of `spectrum` in Hz
+    pub freq_step: f32,
+}
+
+impl AudioFeatures {
+    /// Features of silence
+    pub fn new(band_layout: Arc<BandLayout>) -> AudioFeatures {
+        AudioFeatures {
+            rms: 0.0,
+            peak: 0.0,
+
+            bands: Bands::new(band_layout),
+            channel_bands: vec![],
+            bass: 0.0,
+            mid: 0.0,
+            treble: 0.0,
+
+            centroid: 0.0,
+            flatness: 0.0,
+            flux: 0.0,
+
+            onset: None,
+            tempo: None,
+            beat: false,
+
+            spectrum: vec![],
+            freq_step: 0.0,
+        }
+    }
+
+    /// Derives everything that only depends on the spectra, as the analyzer
+    /// returns them
+    pub fn update_spectra(
+        &mut self,
+        spectrum: &[f32],
+        channel_spectra: &[Vec<f32>],
+        freq_step: f32,
+    ) {
+        self.spectrum.clear();
+        self.spectrum.extend_from_slice(spectrum);
+        self.freq_step = freq_step;
+
+        self.bands.update(spectrum, freq_step);
+        let layout = self.bands.layout();
+        self.channel_bands
+            .resize_with(channel_spectra.len(), || Bands::new(Arc::clone(layout)));
+        for (bands, spectrum) in self.channel_bands.iter_mut().zip(channel_spectra) {
+            bands.update(spectrum, freq_step);
+        }
+
+        self.bass = range_level(spectrum, freq_step, BASS_RANGE);
+        self.mid = range_level(spectrum, freq_step, MID_RANGE);
+        self.treble = range_level(spectrum, freq_step, TREBLE_RANGE);
+        self.centroid = centroid(spectrum, freq_step);
+        self.flatness = flatness(spectrum);
+    }
+
+    /// Call after setting `onset` for the frame
+    pub fn update_tempo(&mut self, tempo: Option<Tempo>) {
+        self.beat = match (tempo, self.tempo) {
+            (Some(tempo), Some(previous)) => tempo.beat != previous.beat,
+            _ => self.onset.is_some(),
+        };
+        self.tempo = tempo;
+    }
+}
+
+/// Accumulates the level of the samples analyzed between two frames
+#[derive(Default)]
+pub struct LevelMeter {
+    sum_of_squares: f64,
+    sample_count: usize,
+    peak: f32,
+}
+
+impl LevelMeter {
+    pub fn add(&mut self, samples: &[f32]) {
+        for sample in samples {
+            self.sum_of_squares += (*sample as f64).powi(2);
+            self.peak = self.peak.max(sample.abs());
+        }
+        self.sample_count += samples.len();
+    }
+
+    /// Returns RMS and peak of the samples added since the last call
+    pub fn take(&mut self) -> (f32, f32) {
+        let rms = if self.sample_count > 0 {
+            (self.sum_of_squares / self.sample_count as f64).sqrt() as f32
+        } else {
+            0.0
+        };
+        let peak = self.peak;
+        *self = LevelMeter::default();
+        (rms, peak)
+    }
+}
+
+fn range_level(spectrum: &[f32], freq_step: f32, (low, high): (f32, f32)) -> f32 {
+    let first = ((low / freq_step).ceil() as usize).min(spectrum.len());
+    let last = ((high / freq_step).ceil() as usize).clamp(first, spectrum.len());
+    spectrum[first..last]
+        .iter()
+        .map(|m| m * m)
+        .sum::<f32>()
+        .sqrt()
+}
+
+/// Weighted by power, so leakage around loud tones barely counts
+fn centroid(spectrum: &[f32], freq_step: f32) -> f32 {
+    let total: f32 = spectrum.iter().map(|m| m * m).sum();
+    if total <= 0.0 {
+        return 0.0;
+    }
+
+    let weighted: f32 = spectrum
+        .iter()
+        .enumerate()
+        .map(|(bin, m)| bin as f32 * freq_step * m * m)
+        .sum();
+    weighted / total
+}
+
+/// Geometric over arithmetic mean of the power spectrum, leaving out DC
+fn flatness(spectrum: &[f32]) -> f32 {
+    let powers = spectrum.iter().skip(1).map(|m| m * m + 1e-12);
+    let count = spectrum.len().saturating_sub(1) as f32;
+    let arithmetic = powers.clone().sum::<f32>() / count;
+    if count == 0.0 || arithmetic <= 1e-10 {
+        return 0.0;
+    }
+
+    let geometric = (powers.map(f32::ln).sum::<f32>() / count).exp();
+    (geometric / arithmetic).min(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::testing::{self, SAMPLE_RATE, WINDOW_SIZE};
+    use crate::generator::Signal;
+
+    fn features_of(signal: Signal) -> AudioFeatures {
+        let analyzed = testing::analyze(signal, 0.5, 4096);
+        let (_, spectrum) = analyzed.spectra.last().unwrap();
+        let mut features = AudioFeatures::new(Arc::new(BandLayout::default()));
+        features.update_spectra(spectrum, &[], SAMPLE_RATE as f32 / WINDOW_SIZE as f32);
+
+        let mut meter = LevelMeter::default();
+        meter.add(&analyzed.samples);
+        (features.rms, features.peak) = meter.take();
+        features
+    }
+
+    #[test]
+    fn describes_tones_and_noise() {
+        let tone = features_of(Signal::Sine { freq: 100.0 });
+        assert!((tone.rms - 0.5 / 2.0f32.sqrt()).abs() < 0.01);
+        assert!((tone.peak - 0.5).abs() < 0.01);
+        assert!(tone.bass > 0.4 && tone.treble < 0.01);
+        assert!((tone.centroid - 100.0).abs() < 20.0);
+        assert!(tone.flatness < 0.01);
+
+        let noise = features_of(Signal::WhiteNoise);
+        assert!(noise.treble > noise.bass);
+        assert!(noise.centroid > 5000.0);
+        assert!(noise.flatness > 0.3);
+
+        let silence = features_of(Signal::Silence);
+        assert_eq!(silence.centroid, 0.0);
+        assert_eq!(silence.flatness, 0.0);
+    }
+}
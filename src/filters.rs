@@ -0,0 +1,187 @@
+use std::f32::consts::PI;
+
+use serde::Deserialize;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilterKind {
+    Highpass,
+    Lowpass,
+    Bandpass,
+}
+
+/// Settings of one biquad in the pre-analysis filter chain
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct FilterStage {
+    pub kind: FilterKind,
+    /// Cutoff frequency, or center frequency for band-pass filters, in Hz
+    pub freq: f32,
+    #[serde(default = "default_q")]
+    pub q: f32,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_q() -> f32 {
+    std::f32::consts::FRAC_1_SQRT_2
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Transposed direct form II biquad with coefficients from the Audio EQ
+/// Cookbook
+#[derive(Clone, Default)]
+struct Biquad {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+    z1: f32,
+    z2: f32,
+}
+
+impl Biquad {
+    /// Keeps the filter state, so settings can change while audio flows
+    fn design(&mut self, stage: &FilterStage, sample_rate: u32) {
+        let nyquist = sample_rate as f32 / 2.0;
+        let w0 = 2.0 * PI * stage.freq.clamp(1.0, nyquist * 0.99) / sample_rate as f32;
+        let alpha = w0.sin() / (2.0 * stage.q.max(0.01));
+        let cos_w0 = w0.cos();
+
+        let (b0, b1, b2) = match stage.kind {
+            FilterKind::Highpass => ((1.0 + cos_w0) / 2.0, -(1.0 + cos_w0), (1.0 + cos_w0) / 2.0),
+            FilterKind::Lowpass => ((1.0 - cos_w0) / 2.0, 1.0 - cos_w0, (1.0 - cos_w0) / 2.0),
+            // Constant 0 dB peak gain
+            FilterKind::Bandpass => (alpha, 0.0, -alpha),
+        };
+        let a0 = 1.0 + alpha;
+        self.b0 = b0 / a0;
+        self.b1 = b1 / a0;
+        self.b2 = b2 / a0;
+        self.a1 = -2.0 * cos_w0 / a0;
+        self.a2 = (1.0 - alpha) / a0;
+    }
+
+    fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let input = *sample;
+            let output = self.b0 * input + self.z1;
+            self.z1 = self.b1 * input - self.a1 * output + self.z2;
+            self.z2 = self.b2 * input - self.a2 * output;
+            *sample = output;
+        }
+    }
+}
+
+/// Applies the input gain and the filter stages to a stream of blocks,
+/// keeping separate filter state per channel.
+#[derive(Default)]
+pub struct FilterChain {
+    stages: Vec<FilterStage>,
+    sample_rate: u32,
+    /// Indexed by channel, then stage
+    biquads: Vec<Vec<Biquad>>,
+}
+
+impl FilterChain {
+    /// Picks up changed settings. Call it before every batch of blocks.
+    pub fn configure(&mut self, stages: &[FilterStage], sample_rate: u32, channel_count: usize) {
+        let same_layout = self.biquads.len() == channel_count && self.stages.len() == stages.len();
+        if same_layout && self.stages == stages && self.sample_rate == sample_rate {
+            return;
+        }
+
+        if !same_layout {
+            self.biquads = vec![vec![Biquad::default(); stages.len()]; channel_count];
+        }
+        for channel in &mut self.biquads {
+            for (biquad, stage) in channel.iter_mut().zip(stages) {
+                biquad.design(stage, sample_rate);
+            }
+        }
+        self.stages = stages.to_vec();
+        self.sample_rate = sample_rate;
+    }
+
+    /// Forgets the past samples of the channel, e.g. after a gap in them
+    pub fn reset(&mut self, channel: usize) {
+        if let Some(biquads) = self.biquads.get_mut(channel) {
+            for biquad in biquads {
+                (biquad.z1, biquad.z2) = (0.0, 0.0);
+            }
+        }
+    }
+
+    pub fn process(&mut self, channel: usize, gain: f32, samples: &mut [f32]) {
+        if gain != 1.0 {
+            samples.iter_mut().for_each(|sample| *sample *= gain);
+        }
+
+        if let Some(biquads) = self.biquads.get_mut(channel) {
+            for (biquad, stage) in biquads.iter_mut().zip(&self.stages) {
+                if stage.enabled {
+                    biquad.process(samples);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peak_after_settling(chain: &mut FilterChain, freq: f32) -> f32 {
+        let mut samples: Vec<f32> = (0..4410)
+            .map(|i| (2.0 * PI * freq * i as f32 / 44100.0).cos())
+            .collect();
+        chain.process(0, 1.0, &mut samples);
+        samples[2205..]
+            .iter()
+            .fold(0.0, |peak, s| peak.max(s.abs()))
+    }
+
+    fn chain_of(kind: FilterKind, freq: f32) -> FilterChain {
+        let mut chain = FilterChain::default();
+        let stage = FilterStage {
+            kind,
+            freq,
+            q: default_q(),
+            enabled: true,
+        };
+        chain.configure(&[stage], 44100, 1);
+        chain
+    }
+
+    #[test]
+    fn high_pass_removes_rumble() {
+        let mut chain = chain_of(FilterKind::Highpass, 100.0);
+
+        assert!(peak_after_settling(&mut chain, 0.0) < 0.01);
+        assert!(peak_after_settling(&mut chain, 10.0) < 0.02);
+        assert!(peak_after_settling(&mut chain, 1000.0) > 0.95);
+    }
+
+    #[test]
+    fn low_pass_removes_treble() {
+        let mut chain = chain_of(FilterKind::Lowpass, 1000.0);
+
+        assert!(peak_after_settling(&mut chain, 0.0) > 0.99);
+        assert!(peak_after_settling(&mut chain, 100.0) > 0.95);
+        assert!(peak_after_settling(&mut chain, 10000.0) < 0.02);
+    }
+
+    #[test]
+    fn band_pass_keeps_the_center_frequency() {
+        let mut chain = chain_of(FilterKind::Bandpass, 1000.0);
+
+        // 0 dB at the center
+        assert!((peak_after_settling(&mut chain, 1000.0) - 1.0).abs() < 0.01);
+        assert!(peak_after_settling(&mut chain, 0.0) < 0.01);
+        assert!(peak_after_settling(&mut chain, 50.0) < 0.1);
+        assert!(peak_after_settling(&mut chain, 15000.0) < 0.1);
+    }
+}
@@ -0,0 +1,203 @@
+use std::sync::{Arc, Mutex};
+
+use crate::analyzer::Analyzer;
+use crate::features::{AudioFeatures, LevelMeter};
+use crate::filters::FilterChain;
+use crate::normalizer::{AgcConfig, Normalizer};
+use crate::onsets::{Beat, OnsetDetector};
+use crate::photonizer::{AnalysisSettings, PhotonizerOptions};
+use crate::ringbuffer::{RingReader, SampleRing};
+use crate::tempo::TempoTracker;
+
+/// Turns the audio that arrived in a ring since the last frame into the
+/// features of the frame. Shared by the live lights and offline renders, so a
+/// render shows what the lights would do.
+pub struct FrameAnalysis {
+    options: Arc<Mutex<PhotonizerOptions>>,
+    analyzer: Analyzer,
+    reader: RingReader,
+    hop_size: usize,
+    sample_rate: u32,
+    /// Frames per second
+    frame_rate: f32,
+    /// Loudest spectra of all hops since the last frame
+    intensities: Vec<f32>,
+    channel_intensities: Vec<Vec<f32>>,
+    hop_intensities: Vec<f32>,
+    normalizer: Normalizer,
+    onsets: OnsetDetector,
+    tempo: TempoTracker,
+    level_meter: LevelMeter,
+    /// Seconds of audio analyzed so far
+    audio_time: f64,
+    filters: FilterChain,
+}
+
+impl FrameAnalysis {
+    pub fn new(
+        options: Arc<Mutex<PhotonizerOptions>>,
+        ring: Arc<SampleRing>,
+        sample_rate: u32,
+        window_size: usize,
+        settings: &AnalysisSettings,
+    ) -> FrameAnalysis {
+        let hop_size = settings.hop_size;
+        let analyzer = Analyzer::new(window_size, settings.window_function);
+        let intensities = vec![0.0f32; analyzer.bin_count()];
+        let analysis = FrameAnalysis {
+            options,
+            analyzer,
+            reader: RingReader::new(ring, window_size, hop_size),
+            hop_size,
+            sample_rate,
+            frame_rate: settings.frame_rate,
+            channel_intensities: vec![intensities.clone()],
+            intensities,
+            hop_intensities: Vec::with_capacity(window_size),
+            normalizer: Normalizer::default(),
+            onsets: OnsetDetector::new(sample_rate as f32 / hop_size as f32),
+            tempo: TempoTracker::new(sample_rate as f32 / hop_size as f32),
+            level_meter: LevelMeter::default(),
+            audio_time: 0.0,
+            filters: FilterChain::default(),
+        };
+        analysis.log_resolution();
+        analysis
+    }
+
+    /// Starts over on the ring of a new audio source. Does nothing if neither
+    /// ring nor sample rate changed.
+    pub fn follow_source(&mut self, ring: Arc<SampleRing>, sample_rate: u32) {
+        if Arc::ptr_eq(&ring, self.reader.ring()) && sample_rate == self.sample_rate {
+            return;
+        }
+
+        self.reader = RingReader::new(ring, self.analyzer.window_size(), self.hop_size);
+        self.filters = FilterChain::default();
+        self.onsets = OnsetDetector::new(sample_rate as f32 / self.hop_size as f32);
+        self.tempo = TempoTracker::new(sample_rate as f32 / self.hop_size as f32);
+        self.sample_rate = sample_rate;
+        self.log_resolution();
+    }
+
+    /// Analyzes all hops that arrived since the last frame
+    pub fn process(&mut self, features: &mut AudioFeatures) {
+        self.transform(features);
+        self.update_features(features);
+    }
+
+    /// Gain the AGC currently applies to the band
+    pub fn agc_gain(&self, config: &AgcConfig, band: usize) -> f32 {
+        self.normalizer.gain(config, band)
+    }
+
+    /// Sets the features that are measured per hop: level, flux and the
+    /// strongest onset since the last frame
+    fn transform(&mut self, features: &mut AudioFeatures) {
+        let input_gain = {
+            let options = self.options.lock().unwrap();
+            let channel_count = self.reader.ring().channel_count();
+            self.filters
+                .configure(&options.filters, self.sample_rate, channel_count);
+            options.input_gain
+        };
+        let filters = &mut self.filters;
+        let mut filter = |channel: usize, hop: &mut [f32], skipped: bool| {
+            // The filter state belongs to the samples before the gap
+            if skipped {
+                filters.reset(channel);
+            }
+            filters.process(channel, input_gain, hop)
+        };
+
+        // Keep the loudest spectrum of all hops since the last frame, so
+        // transients between two frames aren't lost
+        let mut first_hop = true;
+        let mut strongest_onset: Option<Beat> = None;
+        let mut flux = 0.0f32;
+        while self.reader.next_hop_with(&mut filter) {
+            let mid = self.reader.mid();
+            self.level_meter.add(&mid[mid.len() - self.hop_size..]);
+            self.analyzer.transform(mid, &mut self.hop_intensities);
+            hold_peaks(&mut self.intensities, &self.hop_intensities, first_hop);
+
+            self.audio_time += self.hop_size as f64 / self.sample_rate as f64;
+            if let Some(onset) = self.onsets.process(&self.hop_intensities, self.audio_time) {
+                if strongest_onset.is_none_or(|strongest| onset.strength > strongest.strength) {
+                    strongest_onset = Some(onset);
+                }
+            }
+            flux = flux.max(self.onsets.last_flux());
+            self.tempo
+                .process(self.onsets.onset_strength(), self.audio_time);
+
+            let windows = self.reader.windows();
+            self.channel_intensities
+                .resize_with(windows.len(), Vec::new);
+            if windows.len() == 1 {
+                // The mid signal of a mono source is the channel itself
+                hold_peaks(
+                    &mut self.channel_intensities[0],
+                    &self.hop_intensities,
+                    first_hop,
+                );
+            } else {
+                for (samples, held) in windows.iter().zip(self.channel_intensities.iter_mut()) {
+                    self.analyzer.transform(samples, &mut self.hop_intensities);
+                    hold_peaks(held, &self.hop_intensities, first_hop);
+                }
+            }
+
+            first_hop = false;
+        }
+
+        (features.rms, features.peak) = self.level_meter.take();
+        features.flux = flux;
+        features.onset = strongest_onset;
+    }
+
+    /// Sets the features derived from the spectra of the whole frame
+    fn update_features(&mut self, features: &mut AudioFeatures) {
+        let freq_step = self.sample_rate as f32 / self.analyzer.window_size() as f32;
+        features.update_spectra(&self.intensities, &self.channel_intensities, freq_step);
+        features.update_tempo(self.tempo.tempo(self.audio_time));
+
+        // Channels share the gain of the mid signal to keep their balance
+        let agc = self.options.lock().unwrap().agc.clone();
+        self.normalizer
+            .process(&agc, 1.0 / self.frame_rate, features.bands.levels_mut());
+        for channel in features.channel_bands.iter_mut() {
+            self.normalizer.apply(&agc, channel.levels_mut());
+        }
+    }
+
+    /// Logs what the analysis resolves at the sample rate of the source
+    fn log_resolution(&self) {
+        let bucket_count = self.analyzer.window_size() / 2;
+        let freq_step = self.sample_rate as f32 / self.analyzer.window_size() as f32;
+        log::info!(
+            "Analyzing at {} Hz: {} buckets of {} Hz up to {} Hz",
+            self.sample_rate,
+            bucket_count,
+            freq_step,
+            bucket_count as f32 * freq_step
+        );
+        log::info!(
+            "Frame rate: {} Hz ({:.1} hops per frame)",
+            self.frame_rate,
+            self.sample_rate as f32 / self.hop_size as f32 / self.frame_rate
+        );
+    }
+}
+
+fn hold_peaks(held: &mut Vec<f32>, spectrum: &[f32], reset: bool) {
+    if reset || held.len() != spectrum.len() {
+        held.clear();
+        held.extend_from_slice(spectrum);
+        return;
+    }
+
+    for (held, value) in held.iter_mut().zip(spectrum) {
+        *held = held.max(*value);
+    }
+}
@@ -0,0 +1,330 @@
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::SampleRing;
+
+/// Test signals, parsed from strings like `sine:440` or `click:120:80`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum Signal {
+    Sine {
+        freq: f32,
+    },
+    /// Logarithmic sweep, restarting after `duration` seconds
+    Sweep {
+        start_freq: f32,
+        end_freq: f32,
+        duration: f32,
+    },
+    WhiteNoise,
+    PinkNoise,
+    /// Decaying tone burst on every beat
+    Click {
+        bpm: f32,
+        freq: f32,
+    },
+    Silence,
+}
+
+impl FromStr for Signal {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let name = parts.next().unwrap_or_default();
+        let params: Vec<f32> = match parts.map(|p| p.parse::<f32>()).collect() {
+            Ok(params) => params,
+            Err(err) => return Err(format!("Invalid signal parameter in \"{s}\": {err}")),
+        };
+        if params.iter().any(|p| *p <= 0.0) {
+            return Err(format!("Signal parameters must be positive: \"{s}\""));
+        }
+
+        let signal = match (name, params.as_slice()) {
+            ("sine", []) => Signal::Sine { freq: 440.0 },
+            ("sine", [freq]) => Signal::Sine { freq: *freq },
+            ("sweep", []) => Signal::Sweep {
+                start_freq: 20.0,
+                end_freq: 20000.0,
+                duration: 10.0,
+            },
+            ("sweep", [start_freq, end_freq, duration]) => Signal::Sweep {
+                start_freq: *start_freq,
+                end_freq: *end_freq,
+                duration: *duration,
+            },
+            ("white", []) => Signal::WhiteNoise,
+            ("pink", []) => Signal::PinkNoise,
+            ("click", []) => Signal::Click {
+                bpm: 120.0,
+                freq: 80.0,
+            },
+            ("click", [bpm]) => Signal::Click {
+                bpm: *bpm,
+                freq: 80.0,
+            },
+            ("click", [bpm, freq]) => Signal::Click {
+                bpm: *bpm,
+                freq: *freq,
+            },
+            ("silence", []) => Signal::Silence,
+            _ => {
+                return Err(format!(
+                    "Unknown signal \"{s}\", expected one of sine[:HZ], sweep[:FROM_HZ:TO_HZ:SECONDS], white, pink, click[:BPM[:HZ]], silence"
+                ))
+            }
+        };
+
+        Ok(signal)
+    }
+}
+
+impl TryFrom<String> for Signal {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// Produces a test signal sample by sample. Noise is seeded, so every
+/// generator with the same settings yields the same samples.
+pub struct SignalGenerator {
+    signal: Signal,
+    sample_rate: f32,
+    level: f32,
+    rng: StdRng,
+    sample_index: u64,
+    phase: f32,
+    pink_state: [f32; 7],
+}
+
+impl SignalGenerator {
+    pub fn new(signal: Signal, sample_rate: u32, level: f32) -> SignalGenerator {
+        SignalGenerator {
+            signal,
+            sample_rate: sample_rate as f32,
+            level,
+            rng: StdRng::seed_from_u64(0),
+            sample_index: 0,
+            phase: 0.0,
+            pink_state: [0.0; 7],
+        }
+    }
+
+    fn advance_phase(&mut self, freq: f32) -> f32 {
+        let value = (2.0 * PI * self.phase).sin();
+        self.phase = (self.phase + freq / self.sample_rate).fract();
+        value
+    }
+
+    // Paul Kellet's refined pink noise filter
+    // https://www.firstpr.com.au/dsp/pink-noise/
+    fn pink_noise(&mut self) -> f32 {
+        let white = self.rng.gen_range(-1.0f32..1.0);
+        let b = &mut self.pink_state;
+        b[0] = 0.99886 * b[0] + white * 0.0555179;
+        b[1] = 0.99332 * b[1] + white * 0.0750759;
+        b[2] = 0.96900 * b[2] + white * 0.153852;
+        b[3] = 0.86650 * b[3] + white * 0.3104856;
+        b[4] = 0.55000 * b[4] + white * 0.5329522;
+        b[5] = -0.7616 * b[5] - white * 0.016898;
+        let pink = b[0] + b[1] + b[2] + b[3] + b[4] + b[5] + b[6] + white * 0.5362;
+        b[6] = white * 0.115926;
+        // The filter has a gain of roughly 5
+        (pink * 0.2).clamp(-1.0, 1.0)
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        // f32 loses too much precision after a few minutes of runtime
+        let t = self.sample_index as f64 / self.sample_rate as f64;
+        let value = match self.signal.clone() {
+            Signal::Sine { freq } => self.advance_phase(freq),
+            Signal::Sweep {
+                start_freq,
+                end_freq,
+                duration,
+            } => {
+                let progress = ((t % duration as f64) / duration as f64) as f32;
+                self.advance_phase(start_freq * (end_freq / start_freq).powf(progress))
+            }
+            Signal::WhiteNoise => self.rng.gen_range(-1.0..1.0),
+            Signal::PinkNoise => self.pink_noise(),
+            Signal::Click { bpm, freq } => {
+                let since_beat = (t % (60.0 / bpm as f64)) as f32;
+                if since_beat < 1.0 / self.sample_rate {
+                    self.phase = 0.0;
+                }
+                // Decays to -60 dB within 100 ms
+                (-since_beat * 69.0).exp() * self.advance_phase(freq)
+            }
+            Signal::Silence => 0.0,
+        };
+
+        self.sample_index += 1;
+        value * self.level
+    }
+
+    pub fn fill(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+}
+
+/// Plays a generated signal into the analysis in real time
+pub struct GeneratorInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    generator: SignalGenerator,
+    sample_rate: u32,
+    block: Vec<Vec<f32>>,
+}
+
+impl GeneratorInput {
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        signal: Signal,
+        level: f32,
+        sample_rate: u32,
+    ) -> Self {
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = sample_rate;
+            playback_state.open_ring(1)
+        };
+
+        GeneratorInput {
+            playback_state,
+            ring,
+            generator: SignalGenerator::new(signal, sample_rate, level),
+            sample_rate,
+            // Roughly 12 ms, similar to what sound cards deliver
+            block: vec![vec![0.0; 512]],
+        }
+    }
+}
+
+impl AudioSource for GeneratorInput {
+    fn run(&mut self) {
+        let block_duration =
+            Duration::from_secs_f64(self.block[0].len() as f64 / self.sample_rate as f64);
+        let mut next_block = Instant::now();
+
+        loop {
+            self.generator.fill(&mut self.block[0]);
+            self.ring.push(&self.block);
+
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+
+            next_block += block_duration;
+            if let Some(wait) = next_block.checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+}
+
+/// Fixture for the tests of the analysis stages
+#[cfg(test)]
+pub mod testing {
+    use super::{Signal, SignalGenerator};
+    use crate::analyzer::{Analyzer, WindowFunction};
+
+    pub const SAMPLE_RATE: u32 = 44100;
+    pub const WINDOW_SIZE: usize = 1024;
+    pub const HOP_SIZE: usize = 512;
+    /// Spectra per second
+    pub const HOP_RATE: f32 = SAMPLE_RATE as f32 / HOP_SIZE as f32;
+
+    pub struct Analyzed {
+        pub samples: Vec<f32>,
+        /// Of Hann windows one hop apart, with the time each window ends at
+        pub spectra: Vec<(f64, Vec<f32>)>,
+    }
+
+    pub fn analyze(signal: Signal, level: f32, sample_count: usize) -> Analyzed {
+        let mut samples = vec![0.0; sample_count];
+        SignalGenerator::new(signal, SAMPLE_RATE, level).fill(&mut samples);
+
+        let mut analyzer = Analyzer::new(WINDOW_SIZE, WindowFunction::Hann);
+        let spectra = (WINDOW_SIZE..=samples.len())
+            .step_by(HOP_SIZE)
+            .map(|end| {
+                let mut spectrum = vec![];
+                analyzer.transform(&samples[end - WINDOW_SIZE..end], &mut spectrum);
+                (end as f64 / SAMPLE_RATE as f64, spectrum)
+            })
+            .collect();
+        Analyzed { samples, spectra }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_signals() {
+        assert_eq!("sine:100".parse(), Ok(Signal::Sine { freq: 100.0 }));
+        assert_eq!(
+            "click:90".parse(),
+            Ok(Signal::Click {
+                bpm: 90.0,
+                freq: 80.0
+            })
+        );
+        assert!("sine:-5".parse::<Signal>().is_err());
+        assert!("square".parse::<Signal>().is_err());
+    }
+
+    #[test]
+    fn sine_has_requested_frequency() {
+        let mut generator = SignalGenerator::new(Signal::Sine { freq: 100.0 }, 44100, 1.0);
+        let mut samples = vec![0.0; 44100];
+        generator.fill(&mut samples);
+
+        let rising_crossings = samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        // The signal starts at a zero crossing, which doesn't count
+        assert_eq!(rising_crossings, 99);
+    }
+
+    #[test]
+    fn clicks_follow_tempo() {
+        let mut generator = SignalGenerator::new(
+            Signal::Click {
+                bpm: 120.0,
+                freq: 80.0,
+            },
+            1000,
+            1.0,
+        );
+        let mut samples = vec![0.0; 2000];
+        generator.fill(&mut samples);
+
+        // Four beats, each decaying to near silence before the next one
+        for beat in 0..4 {
+            let start = beat * 500;
+            let peak = samples[start..start + 20]
+                .iter()
+                .fold(0.0f32, |a, b| a.max(b.abs()));
+            assert!(peak > 0.5);
+            assert!(samples[start + 400..start + 500]
+                .iter()
+                .all(|v| v.abs() < 0.01));
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use jack::{AudioIn, Client, ClientOptions, ClientStatus, Control, Port, ProcessScope};
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::SampleRing;
+
+// Output ports may show up later, e.g. when the player is started after us
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Runs in JACK's realtime thread, so it must neither lock nor allocate
+struct Capture {
+    ports: Vec<Port<AudioIn>>,
+    blocks: Vec<Vec<f32>>,
+    ring: Arc<SampleRing>,
+}
+
+impl jack::ProcessHandler for Capture {
+    fn process(&mut self, _: &Client, process_scope: &ProcessScope) -> Control {
+        for (port, block) in self.ports.iter().zip(self.blocks.iter_mut()) {
+            block.clear();
+            block.extend_from_slice(port.as_slice(process_scope));
+        }
+        self.ring.push(&self.blocks);
+        Control::Continue
+    }
+
+    fn buffer_size(&mut self, _: &Client, size: jack::Frames) -> Control {
+        for block in &mut self.blocks {
+            block.reserve(size as usize);
+        }
+        Control::Continue
+    }
+}
+
+struct Notifications {
+    server_gone: Arc<AtomicBool>,
+}
+
+impl jack::NotificationHandler for Notifications {
+    fn shutdown(&mut self, _status: ClientStatus, reason: &str) {
+        log::error!("JACK server shut down: {reason}");
+        self.server_gone.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Records from JACK input ports, one per channel
+pub struct JackInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    client: Option<Client>,
+    capture: Option<Capture>,
+    /// Pairs of output port and our input port still to connect
+    pending_connections: Vec<(String, String)>,
+}
+
+impl JackInput {
+    /// Input port `ports[i]` gets connected to output port `connect[i]`
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        client_name: &str,
+        ports: &[String],
+        connect: &[String],
+    ) -> Result<Self, String> {
+        if ports.is_empty() {
+            return Err("JACK input needs at least one port".to_string());
+        }
+        if connect.len() > ports.len() {
+            return Err(format!(
+                "Cannot connect {} JACK ports to {} input ports",
+                connect.len(),
+                ports.len()
+            ));
+        }
+
+        let client = match Client::new(client_name, ClientOptions::NO_START_SERVER) {
+            Ok((client, _status)) => client,
+            Err(err) => return Err(format!("Cannot connect to JACK server: {err}")),
+        };
+
+        let mut input_ports = Vec::new();
+        for name in ports {
+            match client.register_port(name, AudioIn) {
+                Ok(port) => input_ports.push(port),
+                Err(err) => return Err(format!("Cannot register JACK port {name}: {err}")),
+            }
+        }
+
+        let pending_connections = connect
+            .iter()
+            .zip(&input_ports)
+            .map(|(source, port)| (source.clone(), port.name().unwrap_or_default()))
+            .collect();
+
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            // JACK decides the rate for all its clients
+            playback_state.sample_rate = client.sample_rate() as u32;
+            playback_state.open_ring(input_ports.len())
+        };
+
+        let capture = Capture {
+            blocks: vec![Vec::with_capacity(client.buffer_size() as usize); input_ports.len()],
+            ports: input_ports,
+            ring,
+        };
+
+        Ok(JackInput {
+            playback_state,
+            client: Some(client),
+            capture: Some(capture),
+            pending_connections,
+        })
+    }
+
+    fn connect_pending(&mut self, client: &Client) {
+        self.pending_connections.retain(|(source, destination)| {
+            match client.connect_ports_by_name(source, destination) {
+                Ok(()) => {
+                    log::info!("Connected {source} to {destination}");
+                    false
+                }
+                Err(err) => {
+                    log::debug!("Cannot connect {source} to {destination} yet: {err}");
+                    true
+                }
+            }
+        });
+    }
+}
+
+impl AudioSource for JackInput {
+    fn run(&mut self) {
+        let (client, capture) = match (self.client.take(), self.capture.take()) {
+            (Some(client), Some(capture)) => (client, capture),
+            _ => return,
+        };
+
+        let server_gone = Arc::new(AtomicBool::new(false));
+        let notifications = Notifications {
+            server_gone: Arc::clone(&server_gone),
+        };
+        let active_client = match client.activate_async(notifications, capture) {
+            Ok(active_client) => active_client,
+            Err(err) => {
+                log::error!("Cannot activate JACK client: {err}");
+                return;
+            }
+        };
+
+        self.connect_pending(active_client.as_client());
+        let mut connect_attempt = Instant::now();
+        while !server_gone.load(Ordering::Relaxed) {
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+
+            if !self.pending_connections.is_empty()
+                && connect_attempt.elapsed() > CONNECT_RETRY_INTERVAL
+            {
+                self.connect_pending(active_client.as_client());
+                connect_attempt = Instant::now();
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+
+        if let Err(err) = active_client.deactivate() {
+            log::warn!("Cannot deactivate JACK client: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ringbuffer::RingReader;
+
+    #[test]
+    #[ignore = "needs a running JACK server, e.g. jackd -d dummy"]
+    fn records_from_jack() {
+        let playback_state = Arc::new(Mutex::new(PlaybackState::new(1024)));
+        let mut input = JackInput::new(
+            Arc::clone(&playback_state),
+            "krachlicht-test",
+            &["in_1".to_string()],
+            &[],
+        )
+        .unwrap();
+        let ring = Arc::clone(&playback_state.lock().unwrap().ring);
+        let mut reader = RingReader::new(ring, 64, 64);
+
+        let stopper = Arc::clone(&playback_state);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(500));
+            stopper.lock().unwrap().shutdown = true;
+        });
+        input.run();
+        assert!(reader.next_hop());
+    }
+}
@@ -1,57 +1,261 @@
+pub(crate) mod alsainput;
+pub(crate) mod analyzer;
+pub(crate) mod audiofile;
 pub(crate) mod audiosource;
+pub(crate) mod bands;
+pub(crate) mod compresseddecoder;
+pub(crate) mod generator;
 pub(crate) mod intervaltimer;
+#[cfg(feature = "jack")]
+pub(crate) mod jackinput;
+pub(crate) mod mixinput;
 pub(crate) mod effects;
+pub(crate) mod features;
+pub(crate) mod filters;
+pub(crate) mod frameanalysis;
 pub(crate) mod mqtt;
+pub(crate) mod netinput;
+pub(crate) mod normalizer;
 pub(crate) mod olaoutput;
+pub(crate) mod onsets;
 pub(crate) mod osc;
 pub(crate) mod photonizer;
 pub(crate) mod playbackstate;
+pub(crate) mod pulsedevices;
 pub(crate) mod pulseinput;
+pub(crate) mod renderer;
+pub(crate) mod ringbuffer;
+pub(crate) mod sampleconv;
 pub(crate) mod sdlplayer;
-
-use std::path::PathBuf;
+pub(crate) mod silence;
+pub(crate) mod sourcemanager;
+pub(crate) mod stdininput;
+pub(crate) mod tempo;
+pub(crate) mod wavdecoder;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::process;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread;
 
-use clap::Parser;
+use analyzer::WindowFunction;
+use bands::{BandConfig, BandLayout};
+use clap::{Parser, Subcommand};
 use config_file::FromConfigFile;
+use filters::FilterStage;
+use generator::Signal;
 use log;
 use mqtt::MqttClient;
+use netinput::NetFormat;
+use normalizer::AgcConfig;
 use olaoutput::OlaOutput;
 use photonizer::Photonizer;
 use playbackstate::PlaybackState;
-use pulseinput::PulseInput;
-use sdlplayer::SDLPlayer;
+use sampleconv::PcmFormat;
 use serde::Deserialize;
+use silence::IdleConfig;
+use sourcemanager::{SourceConfig, SourceManager};
 
-use crate::audiosource::AudioSource;
 use crate::osc::OscReceiver;
 use crate::osc::OscSender;
-use crate::photonizer::PhotonizerOptions;
+use crate::photonizer::{AnalysisSettings, Mode, PhotonizerOptions, DEFAULT_FRAME_RATE};
+use crate::renderer::{OfflineRenderer, RenderFormat};
+
+const CONFIG_FILE: &str = "krachlicht.toml";
+/// Name of the audio source configured at the top level or on the command line
+const DEFAULT_SOURCE: &str = "default";
+const DEFAULT_WINDOW_FUNCTION: WindowFunction = WindowFunction::Hann;
+const DEFAULT_WINDOW_SIZE: usize = 1024;
+const MIN_WINDOW_SIZE: usize = 64;
+const MAX_WINDOW_SIZE: usize = 16384;
+/// Hops are at least this long and at least a 16th of the window, analyzing
+/// more often costs CPU without showing anything new
+const MIN_HOP_SIZE: usize = 64;
+/// The ring of samples holds at least half a second, five frames at this rate
+const MIN_FRAME_RATE: f32 = 10.0;
+const MAX_FRAME_RATE: f32 = 100.0;
 
 /// krachlicht creates blinkenlights from sound
 #[derive(Parser)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Configuration file path
     #[arg(short, long, value_name = "CONFIG_FILE")]
     config_file_path: Option<PathBuf>,
 
-    /// The s16le wav file to play
+    /// Sample rate to capture and analyze at [default: 44100 for capture
+    /// devices, the native rate of files and streams]
+    #[arg(
+        short = 'r',
+        long,
+        value_name = "HZ",
+        global = true,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    sample_rate: Option<u32>,
+
+    /// Window function applied before each DFT [default: hann]
+    #[arg(long = "window", value_enum, value_name = "WINDOW", global = true)]
+    window_function: Option<WindowFunction>,
+
+    /// Start with this audio source from the configuration file
+    #[arg(long, value_name = "NAME")]
+    source: Option<String>,
+
+    /// The audio file to play (WAV, FLAC, Ogg Vorbis or MP3). Give it several
+    /// times to play a playlist.
     #[arg(short = 'f', long, value_name = "FILE")]
-    sound_file_path: Option<PathBuf>,
+    sound_file_path: Vec<PathBuf>,
+
+    /// Start over after the last file instead of exiting
+    #[arg(long = "loop")]
+    loop_playback: bool,
 
-    /// The PulseAudio device to listen on
+    /// The PulseAudio device to listen on, see list-devices. "default-monitor"
+    /// follows the monitor of the default sink.
     #[arg(short = 'd', long, value_name = "DEVICE")]
     pa_device: Option<String>,
+
+    /// Channel count to record from the PulseAudio device, 1 or 2 [default: 2]
+    #[arg(long, value_name = "CHANNELS")]
+    pa_channels: Option<u16>,
+
+    /// The ALSA device to record from, e.g. "hw:1" or "plughw:CARD=Loopback,DEV=1"
+    #[arg(long, value_name = "DEVICE")]
+    alsa_device: Option<String>,
+
+    /// Channel count to record from the ALSA device [default: 2]
+    #[arg(long, value_name = "CHANNELS")]
+    alsa_channels: Option<u16>,
+
+    /// ALSA period size in frames [default: 256]
+    #[arg(long, value_name = "FRAMES")]
+    alsa_period_size: Option<usize>,
+
+    /// ALSA buffer size in frames [default: 4 periods]
+    #[arg(long, value_name = "FRAMES")]
+    alsa_buffer_size: Option<usize>,
+
+    /// Record from JACK as a client with this name. Needs the "jack" feature.
+    #[arg(long, value_name = "NAME")]
+    jack_client_name: Option<String>,
+
+    /// JACK input port to register, one per channel. Give it several times
+    /// for several channels. [default: left and right]
+    #[arg(long, value_name = "NAME")]
+    jack_port: Vec<String>,
+
+    /// JACK output port to connect to the input port at the same position,
+    /// e.g. "system:capture_1"
+    #[arg(long, value_name = "PORT")]
+    jack_connect: Vec<String>,
+
+    /// Read raw interleaved PCM in this format from stdin
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    stdin_format: Option<PcmFormat>,
+
+    /// Sample rate of the PCM data on stdin [default: 44100]
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(1..))]
+    stdin_sample_rate: Option<u32>,
+
+    /// Channel count of the PCM data on stdin [default: 1]
+    #[arg(long, value_name = "CHANNELS", value_parser = clap::value_parser!(u16).range(1..))]
+    stdin_channels: Option<u16>,
+
+    /// Receive PCM audio via UDP on this address
+    #[arg(long, value_name = "ADDR")]
+    net_listen_addr: Option<String>,
+
+    /// Payload format of received audio packets [default: rtp-l16]
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    net_format: Option<NetFormat>,
+
+    /// Sample rate of received audio [default: 44100]
+    #[arg(long, value_name = "HZ", value_parser = clap::value_parser!(u32).range(1..))]
+    net_sample_rate: Option<u32>,
+
+    /// Channel count of received audio [default: 1]
+    #[arg(long, value_name = "CHANNELS", value_parser = clap::value_parser!(u16).range(1..))]
+    net_channels: Option<u16>,
+
+    /// Analyze a test signal: sine[:HZ], sweep[:FROM_HZ:TO_HZ:SECONDS], white, pink,
+    /// click[:BPM[:HZ]] or silence
+    #[arg(short = 'g', long, value_name = "SIGNAL")]
+    generator: Option<Signal>,
+
+    /// Test signal amplitude in [0; 1] [default: 0.5]
+    #[arg(long, value_name = "LEVEL")]
+    generator_level: Option<f32>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// List the PulseAudio devices to listen on
+    ListDevices,
+
+    /// Analyze an audio file faster than real time and write every frame's pixel colors
+    Render {
+        /// The audio file to analyze
+        file: PathBuf,
+
+        /// Where to write the frames, defaults to stdout
+        #[arg(short, long, value_name = "OUTPUT_FILE")]
+        output: Option<PathBuf>,
+
+        /// The lighting effect to render
+        #[arg(short, long, value_enum, default_value = "pixels")]
+        effect: Mode,
+
+        /// Output file format
+        #[arg(long, value_enum, default_value = "jsonl")]
+        format: RenderFormat,
+
+        /// Seed for effects with random elements
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
 }
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize)]
 struct Config {
-    pa_device: Option<String>,
-    sound_file_path: Option<PathBuf>,
+    sample_rate: Option<u32>,
+    /// Samples per DFT, a power of two
+    window_size: Option<usize>,
+    /// Samples between the starts of two consecutive windows
+    hop_size: Option<usize>,
+    window_function: Option<WindowFunction>,
+    /// Frequency bands the spectrum is divided into
+    bands: Option<BandConfig>,
+    /// Name or index of the band the effects react to
+    effect_band: Option<String>,
+    /// Frames per second analyzed and sent to the lights
+    frame_rate: Option<f32>,
+
+    /// Amplification of the samples before analysis
+    input_gain: Option<f32>,
+    /// Filters applied to the samples before analysis, in order
+    #[serde(default)]
+    filters: Vec<FilterStage>,
+    /// Adaptive normalization of the band levels
+    agc: Option<AgcConfig>,
+    /// What to do when the music stops
+    idle: Option<IdleConfig>,
+
+    /// Source fields at the top level make up the source named "default"
+    #[serde(flatten)]
+    default_source: SourceConfig,
+    /// Additional named sources to switch between at runtime
+    #[serde(default)]
+    sources: BTreeMap<String, SourceConfig>,
+    /// Name of the source to start with
+    source: Option<String>,
 
     osc_listen_addr: String,
     osc_dst_addr: String,
@@ -66,7 +270,7 @@ struct Config {
 fn read_config(args: &Cli) -> Result<Config, String> {
     let config_path = match &args.config_file_path {
         Some(path) => path.to_owned(),
-        None => PathBuf::from_str("krachlicht.toml").unwrap(),
+        None => PathBuf::from_str(CONFIG_FILE).unwrap(),
     };
 
     let config = match Config::from_config_file(config_path) {
@@ -78,23 +282,170 @@ fn read_config(args: &Cli) -> Result<Config, String> {
 }
 
 fn validate_config(args: &Cli, disk_config: &Config) -> Result<Config, String> {
-    if args.pa_device.is_some() && args.sound_file_path.is_some() {
-        return Err(format!(
-            "Must not provide both a PulseAudio device and a sound file"
-        ));
-    }
-
-    let config = Config {
-        pa_device: if args.pa_device.is_some() {
+    let cli_source_count = [
+        args.pa_device.is_some(),
+        args.alsa_device.is_some(),
+        args.jack_client_name.is_some(),
+        !args.sound_file_path.is_empty(),
+        args.stdin_format.is_some(),
+        args.net_listen_addr.is_some(),
+        args.generator.is_some(),
+    ]
+    .iter()
+    .filter(|given| **given)
+    .count();
+
+    // An audio source given on the command line replaces the configured one
+    let use_cli_source = cli_source_count > 0;
+    validate_analysis(disk_config)?;
+
+    let disk_source = &disk_config.default_source;
+    let default_source = SourceConfig {
+        pa_device: if use_cli_source {
             args.pa_device.clone()
         } else {
-            disk_config.pa_device.clone()
+            disk_source.pa_device.clone()
         },
-        sound_file_path: if args.sound_file_path.is_some() {
-            args.sound_file_path.clone()
+        pa_channels: args.pa_channels.or(disk_source.pa_channels),
+        alsa_device: if use_cli_source {
+            args.alsa_device.clone()
         } else {
-            disk_config.sound_file_path.clone()
+            disk_source.alsa_device.clone()
         },
+        alsa_channels: args.alsa_channels.or(disk_source.alsa_channels),
+        alsa_period_size: args.alsa_period_size.or(disk_source.alsa_period_size),
+        alsa_buffer_size: args.alsa_buffer_size.or(disk_source.alsa_buffer_size),
+        jack_client_name: if use_cli_source {
+            args.jack_client_name.clone()
+        } else {
+            disk_source.jack_client_name.clone()
+        },
+        jack_ports: if args.jack_port.is_empty() {
+            disk_source.jack_ports.clone()
+        } else {
+            Some(args.jack_port.clone())
+        },
+        jack_connect: if args.jack_connect.is_empty() {
+            disk_source.jack_connect.clone()
+        } else {
+            Some(args.jack_connect.clone())
+        },
+        sound_file_path: if use_cli_source {
+            args.sound_file_path.first().cloned()
+        } else {
+            disk_source.sound_file_path.clone()
+        },
+        playlist: if use_cli_source {
+            Some(args.sound_file_path.iter().skip(1).cloned().collect())
+        } else {
+            disk_source.playlist.clone()
+        },
+        loop_playback: if args.loop_playback {
+            Some(true)
+        } else {
+            disk_source.loop_playback
+        },
+        stdin_format: if use_cli_source {
+            args.stdin_format
+        } else {
+            disk_source.stdin_format
+        },
+        stdin_sample_rate: args.stdin_sample_rate.or(disk_source.stdin_sample_rate),
+        stdin_channels: args.stdin_channels.or(disk_source.stdin_channels),
+        net_listen_addr: if use_cli_source {
+            args.net_listen_addr.clone()
+        } else {
+            disk_source.net_listen_addr.clone()
+        },
+        net_format: args.net_format.or(disk_source.net_format),
+        net_sample_rate: args.net_sample_rate.or(disk_source.net_sample_rate),
+        net_channels: args.net_channels.or(disk_source.net_channels),
+        generator: if use_cli_source {
+            args.generator.clone()
+        } else {
+            disk_source.generator.clone()
+        },
+        generator_level: args.generator_level.or(disk_source.generator_level),
+        mix: if use_cli_source {
+            None
+        } else {
+            disk_source.mix.clone()
+        },
+        gain: disk_source.gain,
+    };
+
+    let mut sources = disk_config.sources.clone();
+    if default_source.kind_count() > 1 {
+        if default_source.mix.is_some() {
+            return Err("Must not configure other audio sources next to a mix at the top level".to_string());
+        }
+
+        // Several sources at once get mixed
+        let mut mix = Vec::new();
+        for (kind, source) in default_source.split_kinds() {
+            let name = format!("{DEFAULT_SOURCE}-{kind}");
+            sources.insert(name.clone(), source);
+            mix.push(name);
+        }
+        sources.insert(
+            DEFAULT_SOURCE.to_string(),
+            SourceConfig {
+                mix: Some(mix),
+                ..SourceConfig::default()
+            },
+        );
+    } else if default_source.kind_count() == 1 {
+        sources.insert(DEFAULT_SOURCE.to_string(), default_source.clone());
+    }
+    for (name, source) in &sources {
+        if source.kind_count() != 1 {
+            return Err(format!("Audio source \"{name}\" must configure exactly one kind of source"));
+        }
+        if source.stdin_channels == Some(0) || source.net_channels == Some(0) {
+            return Err(format!("Audio source \"{name}\" needs at least one channel"));
+        }
+        if source.stdin_sample_rate == Some(0) || source.net_sample_rate == Some(0) {
+            return Err(format!("Audio source \"{name}\" needs a sample rate above 0 Hz"));
+        }
+        if let Some(level) = source.generator_level {
+            if !(0.0..=1.0).contains(&level) {
+                return Err(format!("Audio source \"{name}\" needs a generator level between 0 and 1"));
+            }
+        }
+    }
+
+    // A source from the command line always wins, otherwise pick one
+    let source = if use_cli_source {
+        DEFAULT_SOURCE.to_string()
+    } else if let Some(source) = args.source.as_ref().or(disk_config.source.as_ref()) {
+        source.clone()
+    } else if sources.contains_key(DEFAULT_SOURCE) {
+        DEFAULT_SOURCE.to_string()
+    } else {
+        match sources.keys().next() {
+            Some(name) => name.clone(),
+            None => return Err("No audio source given".to_string()),
+        }
+    };
+
+    let config = Config {
+        sample_rate: args.sample_rate.or(disk_config.sample_rate),
+        window_size: disk_config.window_size,
+        hop_size: disk_config.hop_size,
+        window_function: args.window_function.or(disk_config.window_function),
+        bands: disk_config.bands.clone(),
+        effect_band: disk_config.effect_band.clone(),
+        frame_rate: disk_config.frame_rate,
+
+        input_gain: disk_config.input_gain,
+        filters: disk_config.filters.clone(),
+        agc: disk_config.agc.clone(),
+        idle: disk_config.idle.clone(),
+
+        default_source,
+        sources,
+        source: Some(source),
+
         osc_listen_addr: disk_config.osc_listen_addr.clone(),
         osc_dst_addr: disk_config.osc_dst_addr.clone(),
 
@@ -108,31 +459,169 @@ fn validate_config(args: &Cli, disk_config: &Config) -> Result<Config, String> {
     return Ok(config);
 }
 
-fn create_player(
+/// Checks the settings shared by the live lights and renders
+fn validate_analysis(config: &Config) -> Result<(), String> {
+    if let Some(0) = config.sample_rate {
+        return Err("Sample rate must not be 0".to_string());
+    }
+
+    let window_size = window_size(config);
+    if !window_size.is_power_of_two() || !(MIN_WINDOW_SIZE..=MAX_WINDOW_SIZE).contains(&window_size)
+    {
+        return Err(format!(
+            "Window size must be a power of two between {MIN_WINDOW_SIZE} and {MAX_WINDOW_SIZE}"
+        ));
+    }
+    if let Some(hop_size) = config.hop_size {
+        let min_hop_size = MIN_HOP_SIZE.max(window_size / 16);
+        if !(min_hop_size..=window_size).contains(&hop_size) {
+            return Err(format!(
+                "Hop size must be between {min_hop_size} and the window size of {window_size}"
+            ));
+        }
+    }
+    if let Some(frame_rate) = config.frame_rate {
+        if !(MIN_FRAME_RATE..=MAX_FRAME_RATE).contains(&frame_rate) {
+            return Err(format!(
+                "Frame rate must be between {MIN_FRAME_RATE} and {MAX_FRAME_RATE} Hz"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Renders use the analysis settings of the configuration file if there is
+/// one, they don't need its sources and outputs
+fn read_render_config(args: &Cli) -> Result<Config, String> {
+    let disk_config = if args.config_file_path.is_none() && !Path::new(CONFIG_FILE).exists() {
+        Config::default()
+    } else {
+        read_config(args)?
+    };
+    validate_analysis(&disk_config)?;
+
+    Ok(Config {
+        sample_rate: args.sample_rate.or(disk_config.sample_rate),
+        window_function: args.window_function.or(disk_config.window_function),
+        ..disk_config
+    })
+}
+
+fn photonizer_options(config: &Config) -> PhotonizerOptions {
+    let mut options = PhotonizerOptions::new();
+    options.input_gain = config.input_gain.unwrap_or(1.0);
+    options.filters = config.filters.clone();
+    options.agc = config.agc.clone().unwrap_or_default();
+    options.idle = config.idle.clone().unwrap_or_default();
+    options
+}
+
+fn window_size(config: &Config) -> usize {
+    config.window_size.unwrap_or(DEFAULT_WINDOW_SIZE)
+}
+
+fn analysis_settings(config: &Config) -> AnalysisSettings {
+    AnalysisSettings {
+        // Half-overlapping windows by default
+        hop_size: config.hop_size.unwrap_or(window_size(config) / 2),
+        window_function: config.window_function.unwrap_or(DEFAULT_WINDOW_FUNCTION),
+        frame_rate: config.frame_rate.unwrap_or(DEFAULT_FRAME_RATE),
+    }
+}
+
+fn band_layout(config: &Config) -> Result<Arc<BandLayout>, String> {
+    match BandLayout::new(
+        &config.bands.clone().unwrap_or_default(),
+        config.effect_band.as_deref(),
+    ) {
+        Ok(band_layout) => Ok(Arc::new(band_layout)),
+        Err(msg) => Err(format!("Invalid frequency bands: {msg}")),
+    }
+}
+
+fn render(
     config: &Config,
-    playback_state: Arc<Mutex<PlaybackState>>,
-) -> Result<Box<dyn AudioSource>, &str> {
-    if let Some(sound_file_path) = config.sound_file_path.as_deref() {
-        return Ok(Box::new(SDLPlayer::new(
-            sound_file_path.to_str().unwrap(),
-            Arc::clone(&playback_state),
-        )));
+    file: &Path,
+    output: Option<&PathBuf>,
+    effect: Mode,
+    format: RenderFormat,
+    seed: u64,
+) -> Result<(), String> {
+    let audio = audiofile::load(file.to_str().unwrap())?;
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => match File::create(path) {
+            Ok(file) => Box::new(BufWriter::new(file)),
+            Err(err) => return Err(format!("Cannot create {}: {}", path.display(), err)),
+        },
+        None => Box::new(BufWriter::new(io::stdout())),
     };
 
-    if let Some(pa_device) = config.pa_device.as_deref() {
-        return Ok(Box::new(PulseInput::new(
-            Arc::clone(&playback_state),
-            pa_device,
-        )));
+    let mut options = photonizer_options(config);
+    options.mode = effect;
+    let mut renderer = OfflineRenderer::new(
+        options,
+        window_size(config),
+        analysis_settings(config),
+        band_layout(config)?,
+        config.sample_rate,
+        format,
+        seed,
+    );
+    match renderer.render(&audio, &mut out) {
+        Ok(frame_count) => {
+            log::info!("Rendered {frame_count} frames");
+            Ok(())
+        }
+        Err(err) => Err(format!("Cannot write frames: {err}")),
+    }
+}
+
+fn list_devices() -> Result<(), String> {
+    for source in pulsedevices::list_sources()? {
+        println!("{}\n    {}", source.name, source.description);
     }
 
-    return Err("No PulseAudio device or audio file given");
+    match pulsedevices::default_monitor() {
+        Ok(monitor) => println!(
+            "{}\n    Currently {monitor}",
+            pulsedevices::DEFAULT_MONITOR
+        ),
+        Err(err) => log::warn!("{err}"),
+    }
+    Ok(())
 }
 
 fn main() {
     env_logger::init();
 
     let args = Cli::parse();
+
+    if let Some(Command::ListDevices) = &args.command {
+        if let Err(msg) = list_devices() {
+            log::error!("{}", msg);
+            process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(Command::Render {
+        file,
+        output,
+        effect,
+        format,
+        seed,
+    }) = &args.command
+    {
+        let res = read_render_config(&args)
+            .and_then(|config| render(&config, file, output.as_ref(), *effect, *format, *seed));
+        if let Err(msg) = res {
+            log::error!("{}", msg);
+            process::exit(1);
+        }
+        return;
+    }
+
     let disk_config = match read_config(&args) {
         Ok(disk_config) => disk_config,
         Err(msg) => {
@@ -148,12 +637,24 @@ fn main() {
         }
     };
 
-    let photonizer_options = Arc::new(Mutex::new(PhotonizerOptions::new()));
+    let photonizer_options = Arc::new(Mutex::new(photonizer_options(&config)));
+    let band_layout = match band_layout(&config) {
+        Ok(band_layout) => band_layout,
+        Err(msg) => {
+            log::error!("{}", msg);
+            process::exit(1);
+        }
+    };
 
-    let window_size = 1024;
-    let playback_state = Arc::new(Mutex::new(PlaybackState::new(window_size)));
-    let mut player = match create_player(&config, Arc::clone(&playback_state)) {
-        Ok(player) => player,
+    let playback_state = Arc::new(Mutex::new(PlaybackState::new(window_size(&config))));
+    let mut source_manager = match SourceManager::new(
+        config.sources.clone(),
+        config.source.as_deref().unwrap_or(DEFAULT_SOURCE),
+        Arc::clone(&playback_state),
+        config.sample_rate,
+        &config.osc_dst_addr,
+    ) {
+        Ok(source_manager) => source_manager,
         Err(err) => {
             log::error!("Cannot set up audio source: {}", err);
             process::exit(1);
@@ -181,10 +682,16 @@ fn main() {
         Arc::clone(&photonizer_options),
         ola,
         osc_sender,
+        analysis_settings(&config),
+        band_layout,
     );
 
     let osc_receiver =
-        match OscReceiver::new(&config.osc_listen_addr, Arc::clone(&photonizer_options)) {
+        match OscReceiver::new(
+            &config.osc_listen_addr,
+            Arc::clone(&photonizer_options),
+            Arc::clone(&playback_state),
+        ) {
             Ok(osc_receiver) => osc_receiver,
             Err(msg) => {
                 log::error!("Cannot set up OSC receiver: {}", msg);
@@ -197,6 +704,7 @@ fn main() {
         &config.mqtt_discovery_prefix,
         &config.mqtt_unique_id,
         Arc::clone(&photonizer_options),
+        Arc::clone(&playback_state),
     ) {
         Ok(mqtt_client) => mqtt_client,
         Err(msg) => {
@@ -244,5 +752,8 @@ fn main() {
         process::exit(1);
     }
 
-    player.run();
+    if let Err(err) = source_manager.run() {
+        log::error!("Cannot set up audio source: {}", err);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,233 @@
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::{RingReader, SampleRing};
+use crate::sampleconv::Resampler;
+use crate::sourcemanager::SourceConfig;
+
+/// Mono members are spread across both channels
+const MIX_CHANNELS: usize = 2;
+const BLOCK_SIZE: usize = 256;
+// Members that lag further behind are padded with silence, so a stalled or
+// drifting member can't hold up the others. Roughly 46 ms at 44.1 kHz.
+const MAX_LAG: usize = 2048;
+
+/// One of the mixed sources, running in its own thread and writing to its own
+/// ring.
+struct Member {
+    name: String,
+    gain: f32,
+    state: Arc<Mutex<PlaybackState>>,
+    reader: RingReader,
+    resamplers: Vec<Resampler>,
+    /// Resampled and amplified frames per mix channel, waiting to be mixed
+    pending: Vec<Vec<f32>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Member {
+    fn start(
+        name: &str,
+        config: SourceConfig,
+        sample_rate: Option<u32>,
+        mix_rate: u32,
+        osc_dst_addr: &str,
+    ) -> Result<Member, String> {
+        let state = Arc::new(Mutex::new(PlaybackState::new(BLOCK_SIZE)));
+        let ring = Arc::clone(&state.lock().unwrap().ring);
+
+        let thread_name = name.to_string();
+        let thread_state = Arc::clone(&state);
+        let osc_dst_addr = osc_dst_addr.to_string();
+        let gain = config.gain.unwrap_or(1.0);
+        // Sources aren't necessarily Send, so each is created in its thread
+        let thread = thread::Builder::new()
+            .name(format!("source {name}"))
+            .spawn(
+                move || match config.create(thread_state, sample_rate, &osc_dst_addr) {
+                    Ok(mut source) => source.run(),
+                    Err(err) => log::error!("Cannot set up audio source \"{thread_name}\": {err}"),
+                },
+            );
+
+        let thread = match thread {
+            Ok(thread) => thread,
+            Err(err) => return Err(format!("Cannot start audio source \"{name}\": {err}")),
+        };
+
+        Ok(Member {
+            name: name.to_string(),
+            gain,
+            state,
+            reader: RingReader::new(ring, BLOCK_SIZE, BLOCK_SIZE),
+            resamplers: (0..MIX_CHANNELS)
+                .map(|_| Resampler::new(mix_rate, mix_rate))
+                .collect(),
+            pending: vec![vec![]; MIX_CHANNELS],
+            thread: Some(thread),
+        })
+    }
+
+    fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Moves everything the member recorded so far to `pending`
+    fn pull(&mut self, mix_rate: u32) {
+        {
+            let state = self.state.lock().unwrap();
+            // The member opened a new ring, e.g. for the next track
+            if !Arc::ptr_eq(self.reader.ring(), &state.ring) {
+                self.reader = RingReader::new(Arc::clone(&state.ring), BLOCK_SIZE, BLOCK_SIZE);
+                self.resamplers = (0..MIX_CHANNELS)
+                    .map(|_| Resampler::new(state.sample_rate, mix_rate))
+                    .collect();
+            }
+        }
+
+        while self.reader.next_hop() {
+            let windows = self.reader.windows();
+            for (channel, resampler) in self.resamplers.iter_mut().enumerate() {
+                let pending = &mut self.pending[channel];
+                let start = pending.len();
+                resampler.process(&windows[channel % windows.len()], pending);
+                for sample in &mut pending[start..] {
+                    *sample *= self.gain;
+                }
+            }
+        }
+    }
+
+    fn stop(&mut self) {
+        self.state.lock().unwrap().shutdown = true;
+        if let Some(thread) = self.thread.take() {
+            if thread.join().is_err() {
+                log::error!("Audio source \"{}\" panicked", self.name);
+            }
+        }
+    }
+}
+
+/// Sums up the frames all members have ready, or pads the ones lagging too
+/// far behind with silence.
+fn take_mixed(pendings: &mut [&mut Vec<Vec<f32>>]) -> Vec<Vec<f32>> {
+    let lengths = pendings.iter().map(|pending| pending[0].len());
+    let ready = lengths.clone().min().unwrap_or(0);
+    let most = lengths.max().unwrap_or(0);
+    let frame_count = if most > MAX_LAG { most } else { ready };
+
+    let mut mixed = vec![vec![0.0; frame_count]; MIX_CHANNELS];
+    for pending in pendings.iter_mut() {
+        for (out, channel) in mixed.iter_mut().zip(pending.iter_mut()) {
+            let available = channel.len().min(frame_count);
+            for (out, sample) in out.iter_mut().zip(channel.drain(..available)) {
+                *out += sample;
+            }
+        }
+    }
+    mixed
+}
+
+/// Mixes several audio sources into one stereo signal at the analysis rate
+pub struct MixInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    mix_rate: u32,
+    members: Vec<Member>,
+}
+
+impl MixInput {
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        members: Vec<(String, SourceConfig)>,
+        sample_rate: Option<u32>,
+        mix_rate: u32,
+        osc_dst_addr: &str,
+    ) -> Result<Self, String> {
+        let mut started = Vec::new();
+        for (name, config) in members {
+            match Member::start(&name, config, sample_rate, mix_rate, osc_dst_addr) {
+                Ok(member) => started.push(member),
+                Err(err) => {
+                    started.iter_mut().for_each(Member::stop);
+                    return Err(err);
+                }
+            }
+        }
+
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = mix_rate;
+            playback_state.open_ring(MIX_CHANNELS)
+        };
+
+        Ok(MixInput {
+            playback_state,
+            ring,
+            mix_rate,
+            members: started,
+        })
+    }
+}
+
+impl AudioSource for MixInput {
+    fn run(&mut self) {
+        loop {
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+
+            for member in &mut self.members {
+                member.pull(self.mix_rate);
+            }
+
+            // Ended members would hold up the others forever
+            let mut pendings: Vec<&mut Vec<Vec<f32>>> = self
+                .members
+                .iter_mut()
+                .filter(|member| member.is_running())
+                .map(|member| &mut member.pending)
+                .collect();
+            if pendings.is_empty() {
+                log::info!("All mixed audio sources ended");
+                break;
+            }
+
+            let mixed = take_mixed(&mut pendings);
+            if !mixed[0].is_empty() {
+                self.ring.push(&mixed);
+            }
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        self.members.iter_mut().for_each(Member::stop);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mixes_ready_frames_and_pads_laggards() {
+        let mut a = vec![vec![1.0, 1.0, 1.0], vec![0.5, 0.5, 0.5]];
+        let mut b = vec![vec![0.25, 0.25], vec![0.25, 0.25]];
+        let mixed = take_mixed(&mut [&mut a, &mut b]);
+        assert_eq!(mixed, vec![vec![1.25, 1.25], vec![0.75, 0.75]]);
+        assert_eq!(a[0].len(), 1);
+        assert!(b[0].is_empty());
+
+        let mut a = vec![vec![1.0; MAX_LAG + 1]; MIX_CHANNELS];
+        let mut b = vec![vec![0.5], vec![0.5]];
+        let mixed = take_mixed(&mut [&mut a, &mut b]);
+        assert_eq!(mixed[0].len(), MAX_LAG + 1);
+        assert_eq!(mixed[0][0], 1.5);
+        assert_eq!(mixed[0][1], 1.0);
+    }
+}
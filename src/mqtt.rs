@@ -1,5 +1,6 @@
 use std::{
     sync::{Arc, Mutex},
+    thread,
     time::Duration,
 };
 
@@ -8,6 +9,9 @@ use mqtt::{Message, Receiver};
 use paho_mqtt as mqtt;
 
 use crate::photonizer::{Mode, PhotonizerOptions};
+use crate::playbackstate::PlaybackState;
+
+const RECEIVE_INTERVAL: Duration = Duration::from_millis(500);
 
 impl From<Mode> for json::JsonValue {
     fn from(mode: Mode) -> Self {
@@ -16,6 +20,7 @@ impl From<Mode> for json::JsonValue {
             Mode::Pixels => "Pixel Flow".into(),
             Mode::Static => "None".into(),
             Mode::Thunderstruck => "Thunderstruck".into(),
+            Mode::Balance => "Balance".into(),
         }
     }
 }
@@ -26,12 +31,17 @@ pub struct MqttClient {
     unique_id: String,
     topics: Topics,
     options: Arc<Mutex<PhotonizerOptions>>,
+    playback_state: Arc<Mutex<PlaybackState>>,
 }
 
 struct Topics {
     state: String,
     state_set: String,
+    tempo: String,
     discovery: String,
+    source_discovery: String,
+    tempo_discovery: String,
+    idle_discovery: String,
 }
 
 impl MqttClient {
@@ -48,11 +58,16 @@ impl MqttClient {
         discovery_prefix: &str,
         unique_id: &str,
         options: Arc<Mutex<PhotonizerOptions>>,
+        playback_state: Arc<Mutex<PlaybackState>>,
     ) -> Result<MqttClient, String> {
         let topics = Topics {
             state: format!("krachlicht/{unique_id}/state"),
             state_set: format!("krachlicht/{unique_id}/state/set"),
+            tempo: format!("krachlicht/{unique_id}/tempo"),
             discovery: format!("{discovery_prefix}/light/{unique_id}/config"),
+            source_discovery: format!("{discovery_prefix}/select/{unique_id}_source/config"),
+            tempo_discovery: format!("{discovery_prefix}/sensor/{unique_id}_tempo/config"),
+            idle_discovery: format!("{discovery_prefix}/binary_sensor/{unique_id}_idle/config"),
         };
 
         let client = match mqtt::Client::new(url) {
@@ -88,10 +103,10 @@ impl MqttClient {
             unique_id: unique_id.to_string(),
             topics,
             options,
+            playback_state,
         };
 
         mqtt_client.publish_discovery();
-        mqtt_client.publish_state();
         Ok(mqtt_client)
     }
 
@@ -118,7 +133,7 @@ impl MqttClient {
             supported_color_modes: json::array! { "rgb" },
 
             effect: true,
-            effect_list: json::array! { "None", "Light Bar", "Pixel Flow", "Thunderstruck" },
+            effect_list: json::array! { "None", "Light Bar", "Pixel Flow", "Thunderstruck", "Balance" },
 
             availability_topic: self.topics.state.to_string(),
             availability_template: "{{ value_json.available }}",
@@ -133,17 +148,142 @@ impl MqttClient {
         if let Err(err) = self.client.publish(msg) {
             log::warn!("Failed to publish HomeAssistant discovery: {err}");
         }
+
+        self.publish_source_discovery();
+        self.publish_tempo_discovery();
+        self.publish_idle_discovery();
     }
 
-    fn publish_state(&self) {
+    /// Audio source selection shows up as a separate select entity, sharing
+    /// the light's state and command topics.
+    fn publish_source_discovery(&self) {
+        let source_names = self.playback_state.lock().unwrap().source_names.clone();
+        let payload = json::object! {
+            device: {
+                identifiers: self.unique_id.to_string(),
+            },
+            unique_id: format!("{}_source", self.unique_id),
+            name: "krachlicht audio source",
+            options: source_names,
+
+            availability_topic: self.topics.state.to_string(),
+            availability_template: "{{ value_json.available }}",
+
+            state_topic: self.topics.state.to_string(),
+            value_template: "{{ value_json.source }}",
+            command_topic: self.topics.state_set.to_string(),
+            command_template: "{\"source\": \"{{ value }}\"}",
+        };
+
+        let payload_str = json::stringify(payload);
+        let msg =
+            mqtt::Message::new_retained(&self.topics.source_discovery, payload_str.clone(), 0);
+        log::info!(
+            "Publishing {}: {}",
+            self.topics.source_discovery,
+            &payload_str
+        );
+        if let Err(err) = self.client.publish(msg) {
+            log::warn!("Failed to publish HomeAssistant discovery: {err}");
+        }
+    }
+
+    /// The tempo shows up as a sensor. Its beat phase changes far too quickly
+    /// for MQTT, it's only sent over OSC.
+    fn publish_tempo_discovery(&self) {
+        let payload = json::object! {
+            device: {
+                identifiers: self.unique_id.to_string(),
+            },
+            unique_id: format!("{}_tempo", self.unique_id),
+            name: "krachlicht tempo",
+            unit_of_measurement: "BPM",
+            icon: "mdi:metronome",
+
+            availability_topic: self.topics.state.to_string(),
+            availability_template: "{{ value_json.available }}",
+
+            state_topic: self.topics.tempo.to_string(),
+            value_template: "{{ value_json.bpm }}",
+        };
+
+        let payload_str = json::stringify(payload);
+        let msg = mqtt::Message::new_retained(&self.topics.tempo_discovery, payload_str.clone(), 0);
+        log::info!(
+            "Publishing {}: {}",
+            self.topics.tempo_discovery,
+            &payload_str
+        );
+        if let Err(err) = self.client.publish(msg) {
+            log::warn!("Failed to publish HomeAssistant discovery: {err}");
+        }
+    }
+
+    /// Silence detection shows up as a binary sensor, on while the lights show
+    /// the idle action
+    fn publish_idle_discovery(&self) {
+        let payload = json::object! {
+            device: {
+                identifiers: self.unique_id.to_string(),
+            },
+            unique_id: format!("{}_idle", self.unique_id),
+            name: "krachlicht idle",
+            icon: "mdi:volume-off",
+
+            availability_topic: self.topics.state.to_string(),
+            availability_template: "{{ value_json.available }}",
+
+            state_topic: self.topics.state.to_string(),
+            value_template: "{{ 'ON' if value_json.idle else 'OFF' }}",
+        };
+
+        let payload_str = json::stringify(payload);
+        let msg = mqtt::Message::new_retained(&self.topics.idle_discovery, payload_str.clone(), 0);
+        log::info!(
+            "Publishing {}: {}",
+            self.topics.idle_discovery,
+            &payload_str
+        );
+        if let Err(err) = self.client.publish(msg) {
+            log::warn!("Failed to publish HomeAssistant discovery: {err}");
+        }
+    }
+
+    /// Publishes the tempo rounded to whole BPM, or null without a beat.
+    /// Returns the published tempo.
+    fn publish_tempo(&self) -> Option<u32> {
+        let bpm = self
+            .playback_state
+            .lock()
+            .unwrap()
+            .tempo
+            .map(|tempo| tempo.bpm.round() as u32);
+        let payload = json::object! {
+            bpm: bpm,
+        };
+
+        let msg = mqtt::Message::new(&self.topics.tempo, json::stringify(payload), 0);
+        if let Err(err) = self.client.publish(msg) {
+            log::debug!("Publishing tempo failed: {err}");
+        }
+
+        bpm
+    }
+
+    /// Returns the published active source, enabled and idle states
+    fn publish_state(&self) -> (String, bool, bool) {
         // TODO Is this even needed?
         if !self.client.is_connected() {
             if let Err(err) = self.client.reconnect() {
                 log::warn!("Reconnection failed: {err}");
-                return;
+                return (String::new(), false, false);
             }
         }
 
+        let (active_source, idle) = {
+            let playback_state = self.playback_state.lock().unwrap();
+            (playback_state.active_source.clone(), playback_state.idle)
+        };
         let options = self.options.lock().unwrap();
         let accent_rgb = options.accent_color.into_components();
         let payload = json::object! {
@@ -156,6 +296,8 @@ impl MqttClient {
                 b: (accent_rgb.2 * 255 as f32) as u8,
             },
             effect: options.mode,
+            source: active_source.clone(),
+            idle: idle,
         };
 
         let payload_str = json::stringify(payload);
@@ -163,21 +305,68 @@ impl MqttClient {
         log::info!("Publishing {}: {}", self.topics.state, &payload_str);
         if let Err(err) = self.client.publish(msg) {
             log::warn!("Publishing failed: {err}");
-            return;
         }
+
+        (active_source, options.enabled, idle)
     }
 
     pub fn run(&self) {
+        let mut published_state = self.publish_state();
+        let mut published_bpm = self.publish_tempo();
         loop {
-            match self.receiver.recv() {
-                Ok(msg) => {
-                    if let Some(msg) = msg {
-                        self.handle_message(msg);
-                        self.publish_state();
-                    }
+            // Wake up regularly to notice source switches made over OSC
+            match self.receiver.recv_timeout(RECEIVE_INTERVAL) {
+                Ok(Some(msg)) => {
+                    self.handle_message(msg);
+                    published_state = self.publish_state();
+                }
+                // Sent when the connection is lost, publishing reconnects
+                Ok(None) => {}
+                Err(err) if err.is_timeout() => {}
+                Err(err) => {
+                    log::warn!("Error receiving messages: {err}");
+                    thread::sleep(RECEIVE_INTERVAL);
+                    self.reconnect();
                 }
-                Err(err) => log::warn!("Error receiving messages: {err}"),
+            }
+
+            // Silence detection switches the lights off and on by itself
+            let enabled = self.options.lock().unwrap().enabled;
+            let state = {
+                let playback_state = self.playback_state.lock().unwrap();
+                (
+                    playback_state.active_source.clone(),
+                    enabled,
+                    playback_state.idle,
+                )
             };
+            if state != published_state {
+                published_state = self.publish_state();
+            }
+
+            let bpm = self
+                .playback_state
+                .lock()
+                .unwrap()
+                .tempo
+                .map(|tempo| tempo.bpm.round() as u32);
+            if bpm != published_bpm {
+                published_bpm = self.publish_tempo();
+            }
+        }
+    }
+
+    fn reconnect(&self) {
+        if let Err(err) = self.client.reconnect() {
+            log::warn!("Reconnection failed: {err}");
+            return;
+        }
+        if let Err(err) = self.client.subscribe(&self.topics.state_set, 0) {
+            log::warn!(
+                "Failed to subscribe to topic {}: {:?}",
+                &self.topics.state_set,
+                err
+            );
         }
     }
 
@@ -238,6 +427,17 @@ impl MqttClient {
             }
         }
 
+        if json.has_key("source") {
+            match json["source"].as_str() {
+                Some(source) => {
+                    if let Err(err) = self.playback_state.lock().unwrap().request_source(source) {
+                        log::warn!("{err}");
+                    }
+                }
+                None => log::warn!("Unexpected source value: {}", json["source"]),
+            }
+        }
+
         if json.has_key("effect") {
             match json["effect"].as_str() {
                 Some(effect) => match effect {
@@ -245,6 +445,7 @@ impl MqttClient {
                     "Light Bar" => options.mode = Mode::LightBar,
                     "Pixel Flow" => options.mode = Mode::Pixels,
                     "Thunderstruck" => options.mode = Mode::Thunderstruck,
+                    "Balance" => options.mode = Mode::Balance,
                     &_ => log::warn!("Unexpected effect: {effect}"),
                 },
                 None => log::warn!("Unexpected effect value: {}", json["effect"]),
@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::Deserialize;
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::SampleRing;
+use crate::sampleconv::{self, PcmFormat, Resampler};
+use crate::wavdecoder::i16_to_f32;
+
+/// Payload formats accepted on the network
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum NetFormat {
+    /// RTP with big-endian 16 bit PCM payload (RFC 3551 L16)
+    RtpL16,
+    /// Plain datagrams of s16le PCM
+    S16le,
+    /// Plain datagrams of f32le PCM
+    F32le,
+}
+
+const RTP_HEADER_SIZE: usize = 12;
+
+/// Returns the sequence number and payload of an RTP packet.
+fn parse_rtp(packet: &[u8]) -> Option<(u16, &[u8])> {
+    if packet.len() < RTP_HEADER_SIZE || packet[0] >> 6 != 2 {
+        return None;
+    }
+
+    let has_padding = packet[0] & 0x20 != 0;
+    let has_extension = packet[0] & 0x10 != 0;
+    let csrc_count = (packet[0] & 0x0F) as usize;
+    let sequence = u16::from_be_bytes([packet[2], packet[3]]);
+
+    let mut payload_start = RTP_HEADER_SIZE + 4 * csrc_count;
+    if has_extension {
+        if packet.len() < payload_start + 4 {
+            return None;
+        }
+        let extension_words =
+            u16::from_be_bytes([packet[payload_start + 2], packet[payload_start + 3]]) as usize;
+        payload_start += 4 + 4 * extension_words;
+    }
+
+    let mut payload_end = packet.len();
+    if has_padding {
+        payload_end = payload_end.checked_sub(*packet.last()? as usize)?;
+    }
+
+    if payload_start > payload_end {
+        return None;
+    }
+
+    Some((sequence, &packet[payload_start..payload_end]))
+}
+
+fn decode_l16(payload: &[u8], out: &mut Vec<f32>) {
+    out.extend(
+        payload
+            .chunks_exact(2)
+            .map(|b| i16_to_f32(i16::from_be_bytes([b[0], b[1]]))),
+    );
+}
+
+/// Reorders packets by sequence number and hides lost packets by repeating
+/// the previous one at decreasing volume.
+pub struct JitterBuffer {
+    packets: BTreeMap<u64, Vec<f32>>,
+    highest_seq: Option<u64>,
+    next_seq: u64,
+    target_depth: usize,
+    playing: bool,
+    last_packet: Vec<f32>,
+    concealed_in_row: i32,
+    current: Vec<f32>,
+    current_pos: usize,
+}
+
+impl JitterBuffer {
+    pub fn new(target_depth: usize) -> JitterBuffer {
+        JitterBuffer {
+            packets: BTreeMap::new(),
+            highest_seq: None,
+            next_seq: 0,
+            target_depth: target_depth.max(1),
+            playing: false,
+            last_packet: vec![],
+            concealed_in_row: 0,
+            current: vec![],
+            current_pos: 0,
+        }
+    }
+
+    /// Sequence numbers wrap after 16 bit, extend them relative to the
+    /// highest one seen so far.
+    fn extend_sequence(&mut self, seq: u16) -> u64 {
+        let ext_seq = match self.highest_seq {
+            Some(highest) => {
+                let delta = seq.wrapping_sub(highest as u16) as i16 as i64;
+                (highest as i64 + delta).max(0) as u64
+            }
+            // Leave room below the first packet for reordered predecessors
+            None => seq as u64 + (1 << 16),
+        };
+
+        if self.highest_seq.is_none_or(|highest| ext_seq > highest) {
+            self.highest_seq = Some(ext_seq);
+        }
+        ext_seq
+    }
+
+    pub fn push(&mut self, seq: u16, samples: Vec<f32>) {
+        let ext_seq = self.extend_sequence(seq);
+        if self.playing && ext_seq < self.next_seq {
+            log::debug!("Dropping late packet {seq}");
+            return;
+        }
+        self.packets.insert(ext_seq, samples);
+
+        // Don't let latency grow without bounds if the sender is faster than us
+        while self.packets.len() > self.target_depth * 4 {
+            self.packets.pop_first();
+            if let Some((&first_seq, _)) = self.packets.first_key_value() {
+                self.next_seq = self.next_seq.max(first_seq);
+            }
+        }
+    }
+
+    fn next_packet(&mut self) -> Option<Vec<f32>> {
+        if !self.playing {
+            if self.packets.len() < self.target_depth {
+                return None;
+            }
+            self.playing = true;
+            self.next_seq = *self.packets.first_key_value()?.0;
+        }
+
+        let first_seq = match self.packets.first_key_value() {
+            Some((&first_seq, _)) => first_seq,
+            None => {
+                log::debug!("Jitter buffer underrun");
+                self.playing = false;
+                return None;
+            }
+        };
+
+        self.next_seq += 1;
+        if first_seq == self.next_seq - 1 {
+            let packet = self.packets.pop_first()?.1;
+            self.last_packet.clone_from(&packet);
+            self.concealed_in_row = 0;
+            return Some(packet);
+        }
+
+        self.concealed_in_row += 1;
+        let fade = 0.5f32.powi(self.concealed_in_row);
+        Some(self.last_packet.iter().map(|v| v * fade).collect())
+    }
+
+    /// Fills `out` with the next samples, writing silence while buffering.
+    pub fn fill(&mut self, out: &mut [f32]) {
+        let mut written = 0;
+        while written < out.len() {
+            if self.current_pos >= self.current.len() {
+                match self.next_packet() {
+                    Some(packet) => {
+                        self.current = packet;
+                        self.current_pos = 0;
+                    }
+                    None => {
+                        out[written..].fill(0.0);
+                        return;
+                    }
+                }
+                continue;
+            }
+
+            let count = (out.len() - written).min(self.current.len() - self.current_pos);
+            out[written..written + count]
+                .copy_from_slice(&self.current[self.current_pos..self.current_pos + count]);
+            written += count;
+            self.current_pos += count;
+        }
+    }
+}
+
+/// Receives PCM audio over UDP, either as RTP L16 or as plain datagrams
+pub struct NetInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    sock: UdpSocket,
+    format: NetFormat,
+    input_rate: u32,
+    channels: usize,
+    jitter_buffer: JitterBuffer,
+    raw_seq: u16,
+    recv_buffer: Vec<u8>,
+    resamplers: Vec<Resampler>,
+}
+
+impl NetInput {
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        listen_addr: &(impl ToSocketAddrs + std::fmt::Debug),
+        format: NetFormat,
+        input_rate: u32,
+        channels: u16,
+        sample_rate: Option<u32>,
+    ) -> Result<Self, String> {
+        let sock = match UdpSocket::bind(listen_addr) {
+            Ok(sock) => sock,
+            Err(err) => return Err(format!("Cannot listen on {:?}: {}", listen_addr, err)),
+        };
+        // Wake up regularly to keep playing out concealment and silence
+        if let Err(err) = sock.set_read_timeout(Some(Duration::from_millis(5))) {
+            return Err(err.to_string());
+        }
+
+        // Analyze at the input rate unless a different one was requested
+        let analysis_rate = sample_rate.unwrap_or(input_rate);
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = analysis_rate;
+            playback_state.open_ring(channels as usize)
+        };
+
+        Ok(NetInput {
+            playback_state,
+            ring,
+            sock,
+            format,
+            input_rate,
+            channels: channels as usize,
+            // Three packets of latency absorb typical LAN jitter
+            jitter_buffer: JitterBuffer::new(3),
+            raw_seq: 0,
+            recv_buffer: vec![0u8; 65536],
+            resamplers: (0..channels)
+                .map(|_| Resampler::new(input_rate, analysis_rate))
+                .collect(),
+        })
+    }
+
+    /// Returns false if the socket failed
+    fn receive(&mut self) -> bool {
+        let size = match self.sock.recv(&mut self.recv_buffer) {
+            Ok(size) => size,
+            Err(err)
+                if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut =>
+            {
+                return true;
+            }
+            Err(err) => {
+                log::error!("Cannot receive audio: {err}");
+                return false;
+            }
+        };
+
+        let datagram = &self.recv_buffer[..size];
+        let mut samples = Vec::with_capacity(size / 2);
+        let seq = match self.format {
+            NetFormat::RtpL16 => match parse_rtp(datagram) {
+                Some((seq, payload)) => {
+                    decode_l16(payload, &mut samples);
+                    seq
+                }
+                None => {
+                    log::debug!("Ignoring invalid RTP packet");
+                    return true;
+                }
+            },
+            NetFormat::S16le | NetFormat::F32le => {
+                let pcm_format = if self.format == NetFormat::S16le {
+                    PcmFormat::S16le
+                } else {
+                    PcmFormat::F32le
+                };
+                pcm_format.decode(datagram, &mut samples);
+                // Plain datagrams carry no sequence number, assume arrival order
+                self.raw_seq = self.raw_seq.wrapping_add(1);
+                self.raw_seq
+            }
+        };
+
+        // Drop partial frames, the channels would end up swapped otherwise
+        samples.truncate(samples.len() - samples.len() % self.channels);
+        self.jitter_buffer.push(seq, samples);
+        true
+    }
+
+    fn play_out(&mut self, frame_count: usize) {
+        let mut block = vec![0.0f32; frame_count * self.channels];
+        self.jitter_buffer.fill(&mut block);
+
+        let blocks = sampleconv::deinterleave_resampled(&block, &mut self.resamplers);
+        self.ring.push(&blocks);
+    }
+}
+
+impl AudioSource for NetInput {
+    fn run(&mut self) {
+        // Play out in real time, independent of when packets arrive
+        let start = Instant::now();
+        let mut frames_played = 0u64;
+
+        loop {
+            if !self.receive() {
+                break;
+            }
+
+            let frames_due = (start.elapsed().as_secs_f64() * self.input_rate as f64) as u64;
+            if frames_due > frames_played {
+                self.play_out((frames_due - frames_played) as usize);
+                frames_played = frames_due;
+            }
+
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ringbuffer::RingReader;
+
+    fn rtp_packet(seq: u16, samples: &[i16]) -> Vec<u8> {
+        let mut packet = vec![0x80, 11];
+        packet.extend_from_slice(&seq.to_be_bytes());
+        packet.extend_from_slice(&[0; 8]);
+        for sample in samples {
+            packet.extend_from_slice(&sample.to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn parses_rtp_header() {
+        let packet = rtp_packet(65535, &[1, -1]);
+        let (seq, payload) = parse_rtp(&packet).unwrap();
+        assert_eq!(seq, 65535);
+        assert_eq!(payload, &[0, 1, 0xFF, 0xFF]);
+
+        assert!(parse_rtp(&[0x40; 16]).is_none());
+    }
+
+    #[test]
+    fn jitter_buffer_reorders_and_conceals() {
+        let mut jitter_buffer = JitterBuffer::new(2);
+        jitter_buffer.push(65535, vec![1.0, 1.0]);
+        // Packet 0 got lost, 2 arrives before 1
+        jitter_buffer.push(2, vec![3.0, 3.0]);
+        jitter_buffer.push(1, vec![2.0, 2.0]);
+
+        let mut out = vec![0.0; 8];
+        jitter_buffer.fill(&mut out);
+        assert_eq!(out, vec![1.0, 1.0, 0.5, 0.5, 2.0, 2.0, 3.0, 3.0]);
+
+        // Underrun plays silence until the buffer has refilled
+        jitter_buffer.fill(&mut out[..2]);
+        assert_eq!(&out[..2], &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn receives_on_loopback() {
+        let playback_state = Arc::new(Mutex::new(PlaybackState::new(64)));
+        let mut input = NetInput::new(
+            Arc::clone(&playback_state),
+            &"127.0.0.1:0",
+            NetFormat::RtpL16,
+            44100,
+            1,
+            None,
+        )
+        .unwrap();
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let dst_addr = input.sock.local_addr().unwrap();
+        for seq in 0..3 {
+            sender
+                .send_to(&rtp_packet(seq, &[i16::MAX; 32]), dst_addr)
+                .unwrap();
+        }
+        for _ in 0..3 {
+            assert!(input.receive());
+        }
+
+        let mut reader = RingReader::new(Arc::clone(&input.ring), 64, 64);
+        input.play_out(64);
+        assert!(reader.next_hop());
+        assert!(reader.mid().iter().all(|v| *v == 1.0));
+    }
+}
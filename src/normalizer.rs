@@ -0,0 +1,136 @@
+use serde::Deserialize;
+
+/// Settings of the automatic gain control applied to the band levels
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct AgcConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Time in seconds for the tracked peak to follow a louder level
+    #[serde(default = "default_attack")]
+    pub attack: f32,
+    /// Time in seconds for the tracked peak to fall back to a quieter level
+    #[serde(default = "default_release")]
+    pub release: f32,
+    /// Level the tracked peak of every band is scaled to
+    #[serde(default = "default_target")]
+    pub target: f32,
+    /// Keeps silence and noise from being amplified into full scale
+    #[serde(default = "default_max_gain")]
+    pub max_gain: f32,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_attack() -> f32 {
+    0.05
+}
+
+fn default_release() -> f32 {
+    5.0
+}
+
+fn default_target() -> f32 {
+    0.8
+}
+
+fn default_max_gain() -> f32 {
+    50.0
+}
+
+impl Default for AgcConfig {
+    /// Disabled, the levels pass through unchanged
+    fn default() -> Self {
+        AgcConfig {
+            enabled: false,
+            attack: default_attack(),
+            release: default_release(),
+            target: default_target(),
+            max_gain: default_max_gain(),
+        }
+    }
+}
+
+/// Tracks a running peak per band and scales each band so its peak ends up
+/// at the target level.
+#[derive(Default)]
+pub struct Normalizer {
+    peaks: Vec<f32>,
+}
+
+impl Normalizer {
+    /// Follows the levels of a frame `elapsed` seconds after the previous one,
+    /// then normalizes them
+    pub fn process(&mut self, config: &AgcConfig, elapsed: f32, levels: &mut [f32]) {
+        if self.peaks.len() != levels.len() {
+            self.peaks = vec![0.0; levels.len()];
+        }
+
+        if config.enabled {
+            for (peak, level) in self.peaks.iter_mut().zip(levels.iter()) {
+                let time = if *level > *peak {
+                    config.attack
+                } else {
+                    config.release
+                };
+                let factor = 1.0 - (-elapsed / time.max(f32::EPSILON)).exp();
+                *peak += (level - *peak) * factor;
+            }
+        }
+
+        self.apply(config, levels);
+    }
+
+    /// Normalizes the levels with the current gains but doesn't track them,
+    /// e.g. for the channels of the signal whose mid was passed to `process`
+    pub fn apply(&self, config: &AgcConfig, levels: &mut [f32]) {
+        for (band, level) in levels.iter_mut().enumerate() {
+            *level = (*level * self.gain(config, band)).clamp(0.0, 1.0);
+        }
+    }
+
+    pub fn gain(&self, config: &AgcConfig, band: usize) -> f32 {
+        match self.peaks.get(band) {
+            Some(peak) if config.enabled => {
+                let max_gain = config.max_gain.max(1.0);
+                config.target / peak.max(config.target / max_gain)
+            }
+            _ => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levels_settle_at_target() {
+        let config = AgcConfig {
+            enabled: true,
+            ..AgcConfig::default()
+        };
+        let mut normalizer = Normalizer::default();
+
+        for input in [0.02, 0.4] {
+            let mut levels = [input, 0.0];
+            for _ in 0..60 {
+                levels = [input, 0.0];
+                normalizer.process(&config, 1.0 / 30.0, &mut levels);
+            }
+            assert!((levels[0] - config.target).abs() < 0.01, "{input}");
+            assert_eq!(levels[1], 0.0);
+        }
+        assert_eq!(normalizer.gain(&config, 1), config.max_gain);
+
+        // Getting quieter takes the release time to catch up with
+        let mut levels = [0.04, 0.0];
+        normalizer.process(&config, 1.0 / 30.0, &mut levels);
+        assert!(levels[0] < 0.1);
+
+        let mut levels = [0.5, 0.5];
+        normalizer.apply(&AgcConfig::default(), &mut levels);
+        assert_eq!(levels, [0.5, 0.5]);
+    }
+}
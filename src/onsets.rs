@@ -0,0 +1,182 @@
+use std::collections::VecDeque;
+
+/// Compresses magnitudes logarithmically, so quiet onsets count as well
+const COMPRESSION: f32 = 100.0;
+/// Seconds of past flux the threshold adapts to
+const HISTORY_DURATION: f32 = 0.5;
+/// Flux has to exceed the median of the history by this factor
+const THRESHOLD_FACTOR: f32 = 1.5;
+/// Keeps ripple in sustained tones and noise in near silence from counting as
+/// onsets. Scaled to the flux summed over the bins up to Nyquist.
+const THRESHOLD_OFFSET: f32 = 1.0;
+/// Spectra needed before the threshold is meaningful
+const WARM_UP: usize = 4;
+/// Shortest time between two onsets in seconds, 600 BPM
+const MIN_INTERVAL: f64 = 0.1;
+
+/// A detected onset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Beat {
+    /// Seconds of audio analyzed before the onset
+    pub time: f64,
+    /// How far the onset exceeded the threshold, in ]0; 1]
+    pub strength: f32,
+}
+
+/// Finds onsets by thresholding the spectral flux, the rise in magnitude
+/// between two consecutive spectra, against its recent median.
+pub struct OnsetDetector {
+    previous: Vec<f32>,
+    history: VecDeque<f32>,
+    history_len: usize,
+    /// Sorted copy of `history`, kept to not allocate for every spectrum
+    sorted: Vec<f32>,
+    above_threshold: bool,
+    last_onset: Option<f64>,
+    onset_strength: f32,
+    last_flux: f32,
+}
+
+impl OnsetDetector {
+    /// `spectrum_rate` is the number of spectra passed to `process` per second
+    pub fn new(spectrum_rate: f32) -> OnsetDetector {
+        let history_len = ((spectrum_rate * HISTORY_DURATION).round() as usize).max(1);
+        OnsetDetector {
+            previous: vec![],
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            sorted: Vec::with_capacity(history_len),
+            above_threshold: false,
+            last_onset: None,
+            onset_strength: 0.0,
+            last_flux: 0.0,
+        }
+    }
+
+    /// Takes the next spectrum, analyzed up to `time` seconds
+    pub fn process(&mut self, spectrum: &[f32], time: f64) -> Option<Beat> {
+        let flux = self.flux(spectrum);
+        self.last_flux = flux;
+
+        let threshold = THRESHOLD_FACTOR * self.median() + THRESHOLD_OFFSET;
+        let warmed_up = self.history.len() >= WARM_UP.min(self.history_len);
+        self.onset_strength = if warmed_up {
+            (flux - threshold).max(0.0)
+        } else {
+            0.0
+        };
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(flux);
+
+        // Only the rising edge counts, a long onset is still one onset
+        let was_above = self.above_threshold;
+        self.above_threshold = flux > threshold;
+        if !self.above_threshold || was_above || !warmed_up {
+            return None;
+        }
+        if let Some(last_onset) = self.last_onset {
+            if time - last_onset < MIN_INTERVAL {
+                return None;
+            }
+        }
+
+        self.last_onset = Some(time);
+        Some(Beat {
+            time,
+            strength: 1.0 - threshold / flux,
+        })
+    }
+
+    /// Flux of the last spectrum passed to `process`
+    pub fn last_flux(&self) -> f32 {
+        self.last_flux
+    }
+
+    /// How far the flux of the last spectrum passed to `process` exceeded the
+    /// threshold, zero if it didn't
+    pub fn onset_strength(&self) -> f32 {
+        self.onset_strength
+    }
+
+    fn median(&mut self) -> f32 {
+        if self.history.is_empty() {
+            return 0.0;
+        }
+
+        self.sorted.clear();
+        self.sorted.extend(&self.history);
+        self.sorted.sort_by(f32::total_cmp);
+        self.sorted[self.sorted.len() / 2]
+    }
+
+    fn flux(&mut self, spectrum: &[f32]) -> f32 {
+        let reset = self.previous.len() != spectrum.len();
+        if reset {
+            self.previous = vec![0.0; spectrum.len()];
+        }
+
+        let mut flux = 0.0;
+        for (previous, magnitude) in self.previous.iter_mut().zip(spectrum) {
+            let compressed = (COMPRESSION * magnitude).ln_1p();
+            flux += (compressed - *previous).max(0.0);
+            *previous = compressed;
+        }
+
+        // The first spectrum rises from nothing
+        if reset {
+            0.0
+        } else {
+            flux
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::testing::{self, HOP_RATE, SAMPLE_RATE};
+    use crate::generator::Signal;
+
+    /// Times of all onsets detected in 4 s of the signal
+    fn detect(signal: Signal, level: f32) -> Vec<f64> {
+        let analyzed = testing::analyze(signal, level, SAMPLE_RATE as usize * 4);
+        let mut detector = OnsetDetector::new(HOP_RATE);
+        let mut onsets = vec![];
+        for (time, spectrum) in &analyzed.spectra {
+            if let Some(beat) = detector.process(spectrum, *time) {
+                assert!(beat.strength > 0.0 && beat.strength <= 1.0);
+                onsets.push(beat.time);
+            }
+        }
+        onsets
+    }
+
+    #[test]
+    fn detects_clicks() {
+        for level in [0.05, 0.8] {
+            let onsets = detect(
+                Signal::Click {
+                    bpm: 120.0,
+                    freq: 80.0,
+                },
+                level,
+            );
+            // Clicks every 0.5 s, detected once the window reaches them. The
+            // one right at the start is in the first spectrum already.
+            assert_eq!(onsets.len(), 7, "{level}: {onsets:?}");
+            for (beat, time) in onsets.iter().enumerate() {
+                let delay = time - (beat + 1) as f64 * 0.5;
+                assert!((0.0..0.03).contains(&delay), "{level}: {onsets:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn ignores_sustained_tones_and_noise() {
+        assert!(detect(Signal::Sine { freq: 80.0 }, 0.8).len() <= 1);
+        assert!(detect(Signal::PinkNoise, 0.5).is_empty());
+        assert!(detect(Signal::Silence, 1.0).is_empty());
+    }
+}
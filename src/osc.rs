@@ -7,7 +7,10 @@ use std::{
 use palette::FromColor;
 use rosc::{decoder, encoder, OscMessage, OscPacket, OscType};
 
+use crate::filters::FilterStage;
 use crate::photonizer::{Mode, PhotonizerOptions};
+use crate::playbackstate::{PlaybackState, Skip};
+use crate::tempo::Tempo;
 
 pub struct OscSender {
     sock: UdpSocket,
@@ -17,6 +20,7 @@ pub struct OscSender {
 pub struct OscReceiver {
     sock: UdpSocket,
     options: Arc<Mutex<PhotonizerOptions>>,
+    playback_state: Arc<Mutex<PlaybackState>>,
 }
 
 impl OscSender {
@@ -49,10 +53,8 @@ impl OscSender {
         })
     }
 
+    /// One value per frequency band, however many the layout has
     pub fn send_buckets(&self, intensities: &[f32]) {
-        const BUCKET_COUNT: usize = 12;
-        assert!(intensities.len() == BUCKET_COUNT);
-
         let osc_intensities = intensities.iter().map(|v| OscType::Float(*v)).collect();
         let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
             addr: "/main/graph".to_string(),
@@ -80,10 +82,69 @@ impl OscSender {
         self.send_float_value("/main/pulseSpeed", pulse_speed);
     }
 
+    pub fn send_input_gain(&self, gain: f32) {
+        self.send_float_value("/filter/gain", gain);
+    }
+
+    /// Gain of the band the effects react to
+    pub fn send_agc_gain(&self, gain: f32) {
+        self.send_float_value("/agc/gain", gain);
+    }
+
+    pub fn send_agc_enabled(&self, enabled: bool) {
+        self.send_float_value("/agc/enabled", if enabled { 1.0 } else { 0.0 });
+    }
+
+    /// The beat within the bar counts from 1
+    pub fn send_tempo(&self, tempo: &Tempo) {
+        self.send_float_value("/tempo/bpm", tempo.bpm);
+        self.send_float_value("/tempo/phase", tempo.phase);
+        self.send_float_value("/tempo/beat", (tempo.beat_in_bar() + 1) as f32);
+    }
+
+    pub fn send_filter(&self, index: usize, stage: &FilterStage) {
+        self.send_float_value(&format!("/filter/{index}/freq"), stage.freq);
+        self.send_float_value(&format!("/filter/{index}/q"), stage.q);
+        self.send_float_value(
+            &format!("/filter/{index}/enabled"),
+            if stage.enabled { 1.0 } else { 0.0 },
+        );
+    }
+
+    pub fn send_active_source(&self, name: &str) {
+        self.send_value("/source/active", OscType::String(name.to_string()));
+    }
+
+    pub fn send_track(&self, name: &str) {
+        self.send_value("/player/track", OscType::String(name.to_string()));
+    }
+
+    /// Position in seconds
+    pub fn send_position(&self, seconds: f32) {
+        self.send_float_value("/player/position", seconds);
+    }
+
+    /// Position as a fraction of the track, matching what `/player/seek` takes
+    pub fn send_progress(&self, progress: f32) {
+        self.send_float_value("/player/seek", progress);
+    }
+
+    pub fn send_paused(&self, paused: bool) {
+        self.send_float_value("/player/pause", if paused { 1.0 } else { 0.0 });
+    }
+
+    pub fn send_looping(&self, looping: bool) {
+        self.send_float_value("/player/loop", if looping { 1.0 } else { 0.0 });
+    }
+
     fn send_float_value(&self, addr: &str, v: f32) {
+        self.send_value(addr, OscType::Float(v));
+    }
+
+    fn send_value(&self, addr: &str, v: OscType) {
         let msg_buf = encoder::encode(&OscPacket::Message(OscMessage {
             addr: addr.to_string(),
-            args: vec![OscType::Float(v)],
+            args: vec![v],
         }))
         .unwrap();
         if let Err(err) = self.sock.send_to(&msg_buf, self.dst_addr) {
@@ -96,6 +157,7 @@ impl OscReceiver {
     pub fn new(
         listen_addr: &(impl ToSocketAddrs + std::fmt::Debug),
         options: Arc<Mutex<PhotonizerOptions>>,
+        playback_state: Arc<Mutex<PlaybackState>>,
     ) -> Result<Self, String> {
         let mut addr_iter = match listen_addr.to_socket_addrs() {
             Ok(iter) => iter,
@@ -118,7 +180,11 @@ impl OscReceiver {
             Err(error) => return Err(error.to_string()),
         };
 
-        Ok(OscReceiver { sock, options })
+        Ok(OscReceiver {
+            sock,
+            options,
+            playback_state,
+        })
     }
 
     pub fn run(&self) {
@@ -158,6 +224,25 @@ impl OscReceiver {
     }
 
     fn handle_message(&self, msg: &OscMessage) -> bool {
+        if msg.addr.starts_with("/player/") {
+            return self.handle_player_message(msg);
+        }
+        if msg.addr == "/source/select" {
+            match msg.args.first() {
+                Some(OscType::String(name)) => {
+                    if let Err(err) = self.playback_state.lock().unwrap().request_source(name) {
+                        println!("{} {}", msg.addr, err);
+                    }
+                }
+                _ => println!("{} Missing OSC parameter: string", msg.addr),
+            }
+            return true;
+        }
+
+        if msg.addr.starts_with("/filter/") {
+            return self.handle_filter_message(msg);
+        }
+
         let mut options = self.options.lock().unwrap();
         match msg.addr.as_str() {
             "/main/lightbar" => {
@@ -168,6 +253,10 @@ impl OscReceiver {
                 options.mode = Mode::Pixels;
                 return true;
             }
+            "/main/balance" => {
+                options.mode = Mode::Balance;
+                return true;
+            }
             "/main/static" => {
                 // TODO This could save power by disabling audio analysis
                 options.mode = Mode::Static;
@@ -201,6 +290,13 @@ impl OscReceiver {
                 }
                 return true;
             }
+            "/agc/enabled" => {
+                match self.handle_float_message(msg) {
+                    Ok(value) => options.agc.enabled = value >= 0.5,
+                    Err(msg) => println!("{}", msg),
+                }
+                return true;
+            }
             "/main/pulseSpeed" => {
                 match self.handle_float_message(msg) {
                     Ok(speed) => options.pulse_speed = speed,
@@ -214,6 +310,77 @@ impl OscReceiver {
         }
     }
 
+    fn handle_player_message(&self, msg: &OscMessage) -> bool {
+        let mut playback_state = self.playback_state.lock().unwrap();
+        let transport = &mut playback_state.transport;
+        match msg.addr.as_str() {
+            "/player/pause" => match self.handle_float_message(msg) {
+                Ok(value) => transport.paused = value >= 0.5,
+                Err(msg) => println!("{}", msg),
+            },
+            "/player/loop" => match self.handle_float_message(msg) {
+                Ok(value) => transport.looping = value >= 0.5,
+                Err(msg) => println!("{}", msg),
+            },
+            "/player/seek" => match self.handle_float_message(msg) {
+                Ok(progress) => transport.seek = Some(progress),
+                Err(msg) => println!("{}", msg),
+            },
+            // Buttons send 1.0 on press and 0.0 on release
+            "/player/next" | "/player/previous" => {
+                if let Ok(0.0) = self.handle_float_message(msg) {
+                    return true;
+                }
+                transport.skip = Some(if msg.addr == "/player/next" {
+                    Skip::Next
+                } else {
+                    Skip::Previous
+                });
+            }
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Handles `/filter/gain` and `/filter/<index>/<freq|q|enabled>`
+    fn handle_filter_message(&self, msg: &OscMessage) -> bool {
+        let mut options = self.options.lock().unwrap();
+        if msg.addr == "/filter/gain" {
+            match self.handle_float_message(msg) {
+                Ok(gain) => options.input_gain = gain.max(0.0),
+                Err(msg) => println!("{}", msg),
+            }
+            return true;
+        }
+
+        let mut parts = msg.addr["/filter/".len()..].split('/');
+        let stage = match parts
+            .next()
+            .and_then(|index| index.parse::<usize>().ok())
+            .and_then(|index| options.filters.get_mut(index))
+        {
+            Some(stage) => stage,
+            None => return false,
+        };
+
+        let value = match self.handle_float_message(msg) {
+            Ok(value) => value,
+            Err(msg) => {
+                println!("{}", msg);
+                return true;
+            }
+        };
+        match parts.next() {
+            Some("freq") => stage.freq = value,
+            Some("q") => stage.q = value,
+            Some("enabled") => stage.enabled = value >= 0.5,
+            _ => return false,
+        }
+
+        true
+    }
+
     fn extract_float_argument(&self, msg: &OscMessage, arg: &OscType) -> Result<f32, String> {
         if let OscType::Float(value) = arg {
             return Ok(*value);
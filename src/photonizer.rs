@@ -1,22 +1,28 @@
-extern crate dft;
-
-use dft::{Operation, Plan};
 use palette::LinSrgb;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use crate::effects::lightbar::LightBar;
-use crate::effects::pixelflow::PixelFlow;
+use crate::analyzer::WindowFunction;
+use crate::bands::BandLayout;
+use crate::effects;
+use crate::effects::breathe::Breathe;
 use crate::effects::staticcolor::StaticColor;
-use crate::effects::thunderstruck::Thunderstruck;
 use crate::effects::LightingEffect;
+use crate::features::AudioFeatures;
+use crate::filters::FilterStage;
+use crate::frameanalysis::FrameAnalysis;
 use crate::intervaltimer::IntervalTimer;
+use crate::normalizer::AgcConfig;
 use crate::olaoutput::OlaOutput;
 use crate::osc::OscSender;
 use crate::playbackstate::PlaybackState;
+use crate::silence::{IdleAction, IdleConfig, SilenceDetector};
+
+pub const DEFAULT_FRAME_RATE: f32 = 30.0;
+pub const PIXEL_COUNT: usize = 18;
 
 // TODO Implement as a trait on LinSrgb?
-fn to_dmx(srgb: palette::LinSrgb) -> [u8; 3] {
+pub fn to_dmx(srgb: palette::LinSrgb) -> [u8; 3] {
     let components = srgb.into_components();
     [
         (components.0 * 255 as f32) as u8,
@@ -25,12 +31,22 @@ fn to_dmx(srgb: palette::LinSrgb) -> [u8; 3] {
     ]
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
 pub enum Mode {
     LightBar,
     Pixels,
     Static,
     Thunderstruck,
+    Balance,
+}
+
+/// How the audio is analyzed, fixed at startup
+pub struct AnalysisSettings {
+    /// Samples between the starts of two consecutive windows
+    pub hop_size: usize,
+    pub window_function: WindowFunction,
+    /// Frames per second
+    pub frame_rate: f32,
 }
 
 pub struct PhotonizerOptions {
@@ -42,10 +58,18 @@ pub struct PhotonizerOptions {
     pub master_intensity: f32,
     pub background_intensity: f32,
 
-    // Step value applied every frame
+    // Pixels per frame at the default frame rate
     pub pulse_speed: f32, // TODO Not currently forwarded
     pub accent_color: palette::LinSrgb,
     pub background_color: palette::LinSrgb,
+
+    /// Applied to the samples before analysis
+    pub input_gain: f32,
+    pub filters: Vec<FilterStage>,
+    /// Applied to the band levels after analysis
+    pub agc: AgcConfig,
+    /// What to do when the music stops
+    pub idle: IdleConfig,
 }
 
 impl PhotonizerOptions {
@@ -60,6 +84,11 @@ impl PhotonizerOptions {
             pulse_speed: 0.6,
             accent_color: LinSrgb::new(0.0, 1.0, 0.0),
             background_color: LinSrgb::new(0.0, 0.0, 0.0),
+
+            input_gain: 1.0,
+            filters: vec![],
+            agc: AgcConfig::default(),
+            idle: IdleConfig::default(),
         }
     }
 }
@@ -67,8 +96,14 @@ impl PhotonizerOptions {
 pub struct Photonizer {
     playback_state: Arc<Mutex<PlaybackState>>,
     options: Arc<Mutex<PhotonizerOptions>>,
-    plan: Plan<f32>,
-    window_size: usize,
+    analysis: FrameAnalysis,
+    /// Frames per second
+    frame_rate: f32,
+    band_layout: Arc<BandLayout>,
+    silence: SilenceDetector,
+    /// The action taken since the input went silent
+    idle: Option<IdleAction>,
+    idle_effect: Option<Box<dyn LightingEffect + Send>>,
     timer: IntervalTimer,
     ola: OlaOutput,
     osc: OscSender,
@@ -86,38 +121,57 @@ impl Photonizer {
         options: Arc<Mutex<PhotonizerOptions>>,
         ola: OlaOutput,
         osc: OscSender,
+        settings: AnalysisSettings,
+        band_layout: Arc<BandLayout>,
     ) -> Photonizer {
-        const UPDATE_FREQ_HZ: f32 = 30.0;
-        const PIXEL_COUNT: usize = 18;
-
-        let window_size = {
+        let (window_size, ring, sample_rate) = {
             let playback_state = playback_state.lock().unwrap();
-            playback_state.buffer.capacity()
+            (
+                playback_state.window_size,
+                Arc::clone(&playback_state.ring),
+                playback_state.sample_rate,
+            )
         };
 
-        {
-            let mut playback_state = playback_state.lock().unwrap();
-            (*playback_state).bucket_count = window_size / 2;
-            (*playback_state).freq_step = 44100.0 / window_size as f32;
-            println!(
-                "Buckets: {}\nBucket bandwidth: {} Hz\nMax frequency: {} Hz",
-                playback_state.bucket_count,
-                playback_state.freq_step,
-                playback_state.bucket_count as f32 * playback_state.freq_step
-            );
-        }
+        log::info!(
+            "Hop: {} samples ({:.0} % overlap)",
+            settings.hop_size,
+            100.0 * (window_size - settings.hop_size) as f32 / window_size as f32
+        );
+        let band_names: Vec<&str> = band_layout
+            .bands()
+            .iter()
+            .map(|band| band.name.as_str())
+            .collect();
+        log::info!("Bands: {}", band_names.join(", "));
 
+        let frame_rate = settings.frame_rate;
         Photonizer {
             playback_state,
             options: Arc::clone(&options),
-            plan: Plan::<f32>::new(Operation::Forward, window_size),
-            window_size,
-            timer: IntervalTimer::new(UPDATE_FREQ_HZ, true),
+            analysis: FrameAnalysis::new(
+                Arc::clone(&options),
+                ring,
+                sample_rate,
+                window_size,
+                &settings,
+            ),
+            frame_rate,
+            band_layout,
+            silence: SilenceDetector::default(),
+            idle: None,
+            idle_effect: None,
+            timer: IntervalTimer::new(frame_rate, true),
             ola,
             osc,
 
             pixel_count: PIXEL_COUNT,
-            effect: Box::new(LightBar::new(Arc::clone(&options), PIXEL_COUNT)),
+            effect: effects::create_effect(
+                Mode::LightBar,
+                Arc::clone(&options),
+                PIXEL_COUNT,
+                frame_rate,
+            ),
             last_mode: Mode::LightBar,
             osc_options_sent: Instant::now(),
             blacked_out: false,
@@ -125,15 +179,27 @@ impl Photonizer {
     }
 
     pub fn run(&mut self) {
-        let mut intensities = vec![0.0f32; self.window_size];
+        let mut features = AudioFeatures::new(Arc::clone(&self.band_layout));
 
         loop {
+            let (enabled, mode, idle_config) = {
+                let options = self.options.lock().unwrap();
+                (options.enabled, options.mode, options.idle.clone())
+            };
+            // Lights switched off for silence still listen for the music to
+            // come back
+            let listening = enabled || self.idle == Some(IdleAction::Off);
+            if listening && (mode != Mode::Static || idle_config.enabled) {
+                self.analyze(&mut features);
+                self.update_idle(&idle_config, &features);
+            } else {
+                features.tempo = None;
+            }
+            self.playback_state.lock().unwrap().tempo = features.tempo;
+
             if self.options.lock().unwrap().enabled {
-                if self.options.lock().unwrap().mode != Mode::Static {
-                    self.transform(&mut intensities);
-                }
-                self.photonize(&intensities);
-                self.send_osc(&intensities);
+                self.photonize(&features);
+                self.send_osc(&features);
             } else {
                 self.blackout();
             }
@@ -146,32 +212,66 @@ impl Photonizer {
         }
     }
 
-    fn transform(&mut self, intensities: &mut Vec<f32>) {
-        let mut dft_io_data = self.playback_state.lock().unwrap().buffer.clone();
-        dft::transform(&mut dft_io_data, &self.plan);
-
-        // Normalize results
-        // https://dsp.stackexchange.com/questions/11376/why-are-magnitudes-normalised-during-synthesis-idft-not-analysis-dft
-        // This uses just c.norm() without scaling?!
-        // https://github.com/astro/rust-pulse-simple/blob/master/examples/spectrum/src/main.rs
-        //let scale_factor = 1.0 / (self.window_size as f32);
-        // Chosen by looking at actual output...
-        let scale_factor = 1.0 / 300.0;
-        let limit: f32 = 1.0;
-        *intensities = dft::unpack(&dft_io_data)
-            .iter()
-            .map(|c| limit.min(c.norm() * scale_factor))
-            .collect();
+    /// Analyzes the audio of the current source since the last frame
+    fn analyze(&mut self, features: &mut AudioFeatures) {
+        let (ring, sample_rate) = {
+            let playback_state = self.playback_state.lock().unwrap();
+            (Arc::clone(&playback_state.ring), playback_state.sample_rate)
+        };
+        self.analysis.follow_source(ring, sample_rate);
+        self.analysis.process(features);
+    }
 
-        let _max_intsy = intensities
-            .iter()
-            .reduce(|a, b| if a > b { a } else { b })
-            .unwrap();
-        //println!("max: {}\tbucket[2]: {}", max_intsy, intensities[2]);
+    /// Switches to the idle action after the configured time of silence, and
+    /// back once there's sound again
+    fn update_idle(&mut self, config: &IdleConfig, features: &AudioFeatures) {
+        let silent = self
+            .silence
+            .process(config, features.rms, 1.0 / self.frame_rate);
+        if silent == self.idle.is_some() {
+            return;
+        }
+
+        if silent {
+            log::info!("Silence, going idle: {:?}", config.action);
+            self.idle = Some(config.action);
+            self.idle_effect = match config.action {
+                IdleAction::Static => Some(Box::new(StaticColor::new(
+                    Arc::clone(&self.options),
+                    self.pixel_count,
+                ))),
+                IdleAction::Breathe => Some(Box::new(Breathe::new(
+                    Arc::clone(&self.options),
+                    self.pixel_count,
+                    self.frame_rate,
+                ))),
+                IdleAction::Off => {
+                    self.options.lock().unwrap().enabled = false;
+                    None
+                }
+            };
+        } else {
+            log::info!("Sound again, leaving idle");
+            if self.idle == Some(IdleAction::Off) {
+                self.options.lock().unwrap().enabled = true;
+            }
+            self.idle = None;
+            self.idle_effect = None;
+        }
+        self.playback_state.lock().unwrap().idle = self.idle.is_some();
     }
 
-    fn send_osc(&mut self, intensities: &Vec<f32>) {
-        self.osc.send_buckets(&intensities[1..13]);
+    fn send_osc(&mut self, features: &AudioFeatures) {
+        self.osc.send_buckets(features.bands.levels());
+        if let Some(tempo) = features.tempo {
+            self.osc.send_tempo(&tempo);
+        }
+        {
+            let options = self.options.lock().unwrap();
+            let effect_band = self.band_layout.effect_band();
+            self.osc
+                .send_agc_gain(self.analysis.agc_gain(&options.agc, effect_band));
+        }
 
         // Don't spam the network with current option values, only very new
         // OSC listeners are interested in them.
@@ -181,6 +281,13 @@ impl Photonizer {
             self.osc
                 .send_background_intensity(options.background_intensity);
             self.osc.send_pulse_speed(options.pulse_speed);
+            self.osc.send_input_gain(options.input_gain);
+            self.osc.send_agc_enabled(options.agc.enabled);
+            for (index, stage) in options.filters.iter().enumerate() {
+                self.osc.send_filter(index, stage);
+            }
+            self.osc
+                .send_active_source(&self.playback_state.lock().unwrap().active_source);
 
             self.osc_options_sent = Instant::now();
         }
@@ -194,30 +301,26 @@ impl Photonizer {
         }
     }
 
-    fn photonize(&mut self, intensities: &Vec<f32>) {
+    fn photonize(&mut self, features: &AudioFeatures) {
         let mode = self.options.lock().unwrap().mode;
         if mode != self.last_mode {
-            self.effect = match mode {
-                Mode::LightBar => {
-                    Box::new(LightBar::new(Arc::clone(&self.options), self.pixel_count))
-                }
-                Mode::Pixels => {
-                    Box::new(PixelFlow::new(Arc::clone(&self.options), self.pixel_count))
-                }
-                Mode::Static => Box::new(StaticColor::new(
-                    Arc::clone(&self.options),
-                    self.pixel_count,
-                )),
-                Mode::Thunderstruck => Box::new(Thunderstruck::new(
-                    Arc::clone(&self.options),
-                    self.pixel_count,
-                )),
-            };
+            self.effect = effects::create_effect(
+                mode,
+                Arc::clone(&self.options),
+                self.pixel_count,
+                self.frame_rate,
+            );
 
             self.last_mode = mode;
         }
 
-        let frame = self.effect.step(intensities);
+        // The effect of the mode stays as it is while idle, to carry on
+        // where it left off
+        let effect = match self.idle_effect.as_mut() {
+            Some(idle_effect) => idle_effect,
+            None => &mut self.effect,
+        };
+        let frame = effect.step(features);
         let master_intensity = self.options.lock().unwrap().master_intensity;
         for i in 0..frame.len() {
             self.ola
@@ -1,27 +1,91 @@
+use std::sync::Arc;
+
+use crate::ringbuffer::SampleRing;
+use crate::tempo::Tempo;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Skip {
+    Next,
+    Previous,
+}
+
+/// Remote control of the file player. Other audio sources ignore it.
+#[derive(Clone, Default)]
+pub struct Transport {
+    pub paused: bool,
+    /// Starts over after the last track instead of stopping
+    pub looping: bool,
+    /// Requested position as a fraction of the current track
+    pub seek: Option<f32>,
+    pub skip: Option<Skip>,
+}
+
 #[derive(Clone)]
 pub struct PlaybackState {
     pub shutdown: bool,
+    pub transport: Transport,
+
+    /// Names of all configured audio sources
+    pub source_names: Vec<String>,
+    pub active_source: String,
+    /// Set to stop the active source and switch to another one
+    pub requested_source: Option<String>,
 
-    pub buffer: Vec<f32>,
+    /// Samples of the current audio source, written without locking
+    pub ring: Arc<SampleRing>,
+    /// Sample rate of `ring`, set by the audio source
+    pub sample_rate: u32,
+    pub window_size: usize,
 
-    pub bucket_count: usize,
-    pub freq_step: f32,
+    /// Tempo of the analyzed audio, if it has a beat
+    pub tempo: Option<Tempo>,
+    /// Set while the input is silent and the lights show the idle action
+    pub idle: bool,
 }
 
 impl PlaybackState {
     pub fn new(window_size: usize) -> PlaybackState {
-        let mut buffer = Vec::with_capacity(window_size);
-        for _ in 0..buffer.capacity() {
-            buffer.push(0.0);
-        }
-
         PlaybackState {
             shutdown: false,
+            transport: Transport::default(),
 
-            buffer,
+            source_names: vec![],
+            active_source: String::new(),
+            requested_source: None,
 
-            bucket_count: 0,
-            freq_step: 0.0,
+            ring: Arc::new(SampleRing::new(1, window_size)),
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            window_size,
+
+            tempo: None,
+            idle: false,
         }
     }
+
+    /// Audio sources return from `run` once this is set
+    pub fn should_stop(&self) -> bool {
+        self.shutdown || self.requested_source.is_some()
+    }
+
+    /// Requesting the active source restarts it
+    pub fn request_source(&mut self, name: &str) -> Result<(), String> {
+        if !self.source_names.iter().any(|n| n == name) {
+            return Err(format!("Unknown audio source \"{name}\""));
+        }
+
+        self.requested_source = Some(name.to_string());
+        Ok(())
+    }
+
+    /// Replaces the ring with an empty one for a new audio source. The
+    /// analysis picks it up with its next frame.
+    pub fn open_ring(&mut self, channel_count: usize) -> Arc<SampleRing> {
+        // Leaves the analysis plenty of slack when it runs late, even at the
+        // lowest frame rate
+        let capacity = (self.window_size * 8).max(self.sample_rate as usize / 2);
+        self.ring = Arc::new(SampleRing::new(channel_count, capacity));
+        Arc::clone(&self.ring)
+    }
 }
@@ -0,0 +1,111 @@
+use std::process::Command;
+
+/// Device name that stands for the monitor of the current default sink
+pub const DEFAULT_MONITOR: &str = "default-monitor";
+
+pub struct SourceInfo {
+    pub name: String,
+    pub description: String,
+}
+
+/// pulse_simple cannot query the server, so ask pactl instead
+fn pactl(args: &[&str]) -> Result<String, String> {
+    let output = match Command::new("pactl")
+        .args(args)
+        // Keep the output parseable regardless of the user's locale
+        .env("LC_ALL", "C")
+        .output()
+    {
+        Ok(output) => output,
+        Err(err) => {
+            return Err(format!(
+                "Cannot run pactl, it comes with the PulseAudio utilities: {err}"
+            ))
+        }
+    };
+
+    if !output.status.success() {
+        return Err(format!(
+            "pactl {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+fn parse_sources(pactl_output: &str) -> Vec<SourceInfo> {
+    let mut sources = Vec::new();
+    let mut name = None;
+    for line in pactl_output.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("Name: ") {
+            name = Some(value.to_string());
+        } else if let Some(value) = line.strip_prefix("Description: ") {
+            if let Some(name) = name.take() {
+                sources.push(SourceInfo {
+                    name,
+                    description: value.to_string(),
+                });
+            }
+        }
+    }
+
+    sources
+}
+
+fn parse_default_sink(pactl_output: &str) -> Option<String> {
+    pactl_output
+        .lines()
+        .find_map(|line| line.strip_prefix("Default Sink: "))
+        .map(|sink| sink.trim().to_string())
+}
+
+pub fn list_sources() -> Result<Vec<SourceInfo>, String> {
+    Ok(parse_sources(&pactl(&["list", "sources"])?))
+}
+
+pub fn default_monitor() -> Result<String, String> {
+    match parse_default_sink(&pactl(&["info"])?) {
+        Some(sink) => Ok(format!("{sink}.monitor")),
+        None => Err("PulseAudio has no default sink".to_string()),
+    }
+}
+
+/// Turns `DEFAULT_MONITOR` into an actual device name
+pub fn resolve(device: &str) -> Result<String, String> {
+    if device == DEFAULT_MONITOR {
+        default_monitor()
+    } else {
+        Ok(device.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_pactl_output() {
+        let sources = parse_sources(
+            "Source #0\n\
+             \tState: SUSPENDED\n\
+             \tName: alsa_output.pci-0000_00_1f.3.analog-stereo.monitor\n\
+             \tDescription: Monitor of Built-in Audio Analog Stereo\n\
+             \tProperties:\n\
+             \t\tdevice.description = \"Monitor of Built-in Audio Analog Stereo\"\n\
+             \n\
+             Source #1\n\
+             \tName: alsa_input.usb-mic.mono-fallback\n\
+             \tDescription: USB Microphone Mono\n",
+        );
+        assert_eq!(sources.len(), 2);
+        assert_eq!(sources[1].name, "alsa_input.usb-mic.mono-fallback");
+        assert_eq!(sources[1].description, "USB Microphone Mono");
+
+        let sink = parse_default_sink(
+            "Server Name: pulseaudio\nDefault Sink: alsa_output.usb-dac.analog-stereo\nDefault Source: x\n",
+        );
+        assert_eq!(sink.as_deref(), Some("alsa_output.usb-dac.analog-stereo"));
+    }
+}
@@ -1,50 +1,198 @@
 extern crate pulse_simple;
 
+use std::panic;
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use pulse_simple::Record;
+use pulse_simple::{ChannelCount, Record};
 
-use crate::{audiosource::AudioSource, playbackstate::PlaybackState};
+use crate::{
+    audiosource::AudioSource, playbackstate::PlaybackState, pulsedevices, ringbuffer::SampleRing,
+    sampleconv,
+};
 
-pub struct PulseInput {
-    playback_state: Arc<Mutex<PlaybackState>>,
-    pulse: Record<[f32; 1]>,
-    buffer: Vec<[f32; 1]>,
+// Roughly 6 ms at 44.1 kHz
+const BLOCK_SIZE: usize = 256;
+// How often to look for a new default sink when following it
+const DEFAULT_SINK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+// pulse_simple encodes the channel count in the frame type
+enum Recorder {
+    Mono(Record<[f32; 1]>, Vec<[f32; 1]>),
+    Stereo(Record<[f32; 2]>, Vec<[f32; 2]>),
 }
 
-impl PulseInput {
-    pub fn new(playback_state: Arc<Mutex<PlaybackState>>, device: &str) -> Self {
-        let sample_rate = 44100;
-        let pulse = Record::new(
+/// pulse_simple asserts that the stream opened instead of returning an error.
+/// Checking the device against `pulsedevices::list_sources` beforehand would
+/// reject aliases like `@DEFAULT_SOURCE@`.
+fn record<C: ChannelCount>(device: &str, sample_rate: u32) -> Result<Record<C>, String> {
+    match panic::catch_unwind(|| {
+        Record::new(
             "krachlicht",
             "Live audio analyzer",
-            Some(&device),
+            Some(device),
             sample_rate,
-        );
+        )
+    }) {
+        Ok(record) => Ok(record),
+        Err(_) => Err(format!("Cannot record from PulseAudio source {device}")),
+    }
+}
 
+impl Recorder {
+    /// Records mono or stereo, other channel counts are rejected
+    fn open(device: &str, sample_rate: u32, channels: u16) -> Result<Recorder, String> {
         // Pre-filling is necessary according to pulse_simple example
-        let window_size = playback_state.lock().unwrap().buffer.capacity();
-        let mut buffer = Vec::with_capacity(window_size);
-        for _ in 0..buffer.capacity() {
-            buffer.push([0.0]);
+        match channels {
+            1 => Ok(Recorder::Mono(
+                record(device, sample_rate)?,
+                vec![[0.0]; BLOCK_SIZE],
+            )),
+            2 => Ok(Recorder::Stereo(
+                record(device, sample_rate)?,
+                vec![[0.0; 2]; BLOCK_SIZE],
+            )),
+            _ => Err(format!(
+                "PulseAudio input supports 1 or 2 channels, not {channels}"
+            )),
+        }
+    }
+
+    /// Reads one block and returns it interleaved
+    fn read(&mut self) -> Vec<f32> {
+        match self {
+            Recorder::Mono(pulse, buffer) => {
+                pulse.read(&mut buffer[..]);
+                buffer.iter().flatten().copied().collect()
+            }
+            Recorder::Stereo(pulse, buffer) => {
+                pulse.read(&mut buffer[..]);
+                buffer.iter().flatten().copied().collect()
+            }
+        }
+    }
+}
+
+pub struct PulseInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    recorder: Recorder,
+    /// The configured device, possibly `pulsedevices::DEFAULT_MONITOR`
+    device: String,
+    /// The device actually recorded from
+    resolved_device: String,
+    sample_rate: u32,
+    channels: usize,
+}
+
+impl PulseInput {
+    /// `pulsedevices::DEFAULT_MONITOR` follows the default sink around
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        device: &str,
+        sample_rate: u32,
+        channels: u16,
+    ) -> Result<Self, String> {
+        let resolved_device = pulsedevices::resolve(device)?;
+        if resolved_device != device {
+            log::info!("Recording from {resolved_device}");
         }
+        let recorder = Recorder::open(&resolved_device, sample_rate, channels)?;
 
-        PulseInput {
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = sample_rate;
+            playback_state.open_ring(channels as usize)
+        };
+
+        Ok(PulseInput {
             playback_state,
-            pulse,
-            buffer,
+            ring,
+            recorder,
+            device: device.to_string(),
+            resolved_device,
+            sample_rate,
+            channels: channels as usize,
+        })
+    }
+
+    /// Looks up the default sink on a separate thread, running pactl between
+    /// two reads would hold up recording. Sends the monitor of every new
+    /// default sink.
+    fn watch_default_sink(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        let playback_state = Arc::clone(&self.playback_state);
+        let mut current = self.resolved_device.clone();
+        let res = thread::Builder::new()
+            .name("Default sink".to_string())
+            .spawn(move || {
+                let mut failing = false;
+                loop {
+                    thread::sleep(DEFAULT_SINK_POLL_INTERVAL);
+                    if playback_state.lock().unwrap().should_stop() {
+                        break;
+                    }
+
+                    match pulsedevices::default_monitor() {
+                        Ok(monitor) => {
+                            failing = false;
+                            if monitor == current {
+                                continue;
+                            }
+                            // Fails once recording stopped
+                            if sender.send(monitor.clone()).is_err() {
+                                break;
+                            }
+                            current = monitor;
+                        }
+                        Err(err) => {
+                            if !failing {
+                                log::warn!("Cannot look up the default sink: {err}");
+                                failing = true;
+                            }
+                        }
+                    }
+                }
+            });
+        if let Err(err) = res {
+            log::error!("Failed to create thread: {err}");
+        }
+
+        receiver
+    }
+
+    /// Switches over to the monitor of the new default sink
+    fn follow_default_sink(&mut self, monitor: String) {
+        log::info!("Default sink changed, recording from {monitor}");
+        match Recorder::open(&monitor, self.sample_rate, self.channels as u16) {
+            Ok(recorder) => {
+                self.recorder = recorder;
+                self.resolved_device = monitor;
+            }
+            Err(err) => log::error!("Cannot record from {monitor}: {err}"),
         }
     }
 }
 
 impl AudioSource for PulseInput {
     fn run(&mut self) {
+        let default_sink = if self.device == pulsedevices::DEFAULT_MONITOR {
+            Some(self.watch_default_sink())
+        } else {
+            None
+        };
         loop {
-            self.pulse.read(&mut self.buffer[..]);
-            let mut playback_state = self.playback_state.lock().unwrap();
-            playback_state.buffer = self.buffer.iter().map(|v| v[0]).collect();
+            if let Some(Ok(monitor)) = default_sink.as_ref().map(Receiver::try_recv) {
+                self.follow_default_sink(monitor);
+            }
+
+            let samples = self.recorder.read();
+            self.ring
+                .push(&sampleconv::deinterleave(&samples, self.channels));
 
-            if playback_state.shutdown {
+            if self.playback_state.lock().unwrap().should_stop() {
                 break;
             }
         }
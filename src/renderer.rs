@@ -0,0 +1,250 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::audiofile::DecodedAudio;
+use crate::bands::BandLayout;
+use crate::effects;
+use crate::effects::thunderstruck::Thunderstruck;
+use crate::effects::LightingEffect;
+use crate::features::AudioFeatures;
+use crate::frameanalysis::FrameAnalysis;
+use crate::photonizer::{to_dmx, AnalysisSettings, Mode, PhotonizerOptions, PIXEL_COUNT};
+use crate::ringbuffer::SampleRing;
+use crate::sampleconv;
+
+#[derive(Clone, Copy, Debug, PartialEq, clap::ValueEnum)]
+pub enum RenderFormat {
+    /// One JSON object per frame
+    #[value(name = "jsonl")]
+    JsonLines,
+    /// One row per frame, three columns per pixel
+    Csv,
+}
+
+/// Runs audio through the analysis and an effect as fast as possible instead
+/// of in real time, writing the resulting pixel colors of every frame.
+pub struct OfflineRenderer {
+    window_size: usize,
+    settings: AnalysisSettings,
+    band_layout: Arc<BandLayout>,
+    options: Arc<Mutex<PhotonizerOptions>>,
+    effect: Box<dyn LightingEffect + Send>,
+    format: RenderFormat,
+    sample_rate: Option<u32>,
+}
+
+impl OfflineRenderer {
+    /// Renders the effect of `options.mode`. Analyzes at the file's sample
+    /// rate unless a different one is requested.
+    pub fn new(
+        options: PhotonizerOptions,
+        window_size: usize,
+        settings: AnalysisSettings,
+        band_layout: Arc<BandLayout>,
+        sample_rate: Option<u32>,
+        format: RenderFormat,
+        seed: u64,
+    ) -> OfflineRenderer {
+        let mode = options.mode;
+        let options = Arc::new(Mutex::new(options));
+
+        let effect: Box<dyn LightingEffect + Send> = match mode {
+            Mode::Thunderstruck => Box::new(Thunderstruck::with_seed(
+                Arc::clone(&options),
+                PIXEL_COUNT,
+                settings.frame_rate,
+                seed,
+            )),
+            _ => {
+                effects::create_effect(mode, Arc::clone(&options), PIXEL_COUNT, settings.frame_rate)
+            }
+        };
+
+        OfflineRenderer {
+            window_size,
+            settings,
+            band_layout,
+            options,
+            effect,
+            format,
+            sample_rate,
+        }
+    }
+
+    /// Returns the number of rendered frames
+    pub fn render(&mut self, audio: &DecodedAudio, out: &mut impl Write) -> io::Result<usize> {
+        let analysis_rate = self.sample_rate.unwrap_or(audio.sample_rate);
+        let channel_buffers: Vec<Vec<f32>> =
+            sampleconv::deinterleave(&audio.samples, audio.channels as usize)
+                .iter()
+                .map(|channel| sampleconv::resample(channel, audio.sample_rate, analysis_rate))
+                .collect();
+        let length = channel_buffers.first().map_or(0, Vec::len);
+
+        let (mode, master_intensity) = {
+            let options = self.options.lock().unwrap();
+            (options.mode, options.master_intensity)
+        };
+
+        if self.format == RenderFormat::Csv {
+            self.write_csv_header(out)?;
+        }
+
+        // Before each frame, the ring receives as much audio as plays during
+        // one, like from a source in real time
+        let frame_rate = self.settings.frame_rate;
+        let frame_len = analysis_rate as f64 / frame_rate as f64;
+        let frame_count = (length as f64 / frame_len).ceil() as usize;
+        let ring = Arc::new(SampleRing::new(
+            channel_buffers.len(),
+            self.window_size + frame_len.ceil() as usize,
+        ));
+        let mut analysis = FrameAnalysis::new(
+            Arc::clone(&self.options),
+            Arc::clone(&ring),
+            analysis_rate,
+            self.window_size,
+            &self.settings,
+        );
+        let mut features = AudioFeatures::new(Arc::clone(&self.band_layout));
+        for frame in 0..frame_count {
+            let start = (frame as f64 * frame_len) as usize;
+            let end = (((frame + 1) as f64 * frame_len) as usize).min(length);
+            let blocks: Vec<&[f32]> = channel_buffers
+                .iter()
+                .map(|samples| &samples[start..end])
+                .collect();
+            ring.push(&blocks);
+            if mode != Mode::Static {
+                analysis.process(&mut features);
+            }
+
+            let pixels: Vec<[u8; 3]> = self
+                .effect
+                .step(&features)
+                .into_iter()
+                .map(|color| to_dmx(color * master_intensity))
+                .collect();
+            let time = frame as f64 / frame_rate as f64;
+            match self.format {
+                RenderFormat::JsonLines => self.write_json_frame(out, time, &pixels)?,
+                RenderFormat::Csv => self.write_csv_frame(out, time, &pixels)?,
+            }
+        }
+
+        out.flush()?;
+        Ok(frame_count)
+    }
+
+    fn write_json_frame(
+        &self,
+        out: &mut impl Write,
+        time: f64,
+        pixels: &[[u8; 3]],
+    ) -> io::Result<()> {
+        let payload = json::object! {
+            time: time,
+            pixels: pixels
+                .iter()
+                .map(|rgb| json::array![rgb[0], rgb[1], rgb[2]])
+                .collect::<Vec<json::JsonValue>>(),
+        };
+        writeln!(out, "{}", json::stringify(payload))
+    }
+
+    fn write_csv_header(&self, out: &mut impl Write) -> io::Result<()> {
+        write!(out, "time")?;
+        for i in 0..PIXEL_COUNT {
+            write!(out, ",p{i}_r,p{i}_g,p{i}_b")?;
+        }
+        writeln!(out)
+    }
+
+    fn write_csv_frame(
+        &self,
+        out: &mut impl Write,
+        time: f64,
+        pixels: &[[u8; 3]],
+    ) -> io::Result<()> {
+        write!(out, "{time:.4}")?;
+        for rgb in pixels {
+            write!(out, ",{},{},{}", rgb[0], rgb[1], rgb[2])?;
+        }
+        writeln!(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyzer::WindowFunction;
+    use crate::photonizer::DEFAULT_FRAME_RATE;
+
+    fn renderer(mode: Mode, format: RenderFormat, seed: u64) -> OfflineRenderer {
+        let mut options = PhotonizerOptions::new();
+        options.mode = mode;
+        let settings = AnalysisSettings {
+            hop_size: 512,
+            window_function: WindowFunction::Hann,
+            frame_rate: DEFAULT_FRAME_RATE,
+        };
+        OfflineRenderer::new(
+            options,
+            1024,
+            settings,
+            Arc::new(BandLayout::default()),
+            None,
+            format,
+            seed,
+        )
+    }
+
+    fn bass_burst() -> DecodedAudio {
+        // Half a second of silence followed by half a second of 86 Hz
+        let rate = 44100;
+        let mut samples = vec![0.0f32; rate as usize / 2];
+        for i in 0..rate as usize / 2 {
+            let t = i as f32 / rate as f32;
+            samples.push(0.5 * (2.0 * std::f32::consts::PI * 86.0 * t).sin());
+        }
+
+        DecodedAudio {
+            sample_rate: rate,
+            channels: 1,
+            samples,
+        }
+    }
+
+    #[test]
+    fn lightbar_follows_bass() {
+        let mut renderer = renderer(Mode::LightBar, RenderFormat::Csv, 0);
+        let mut out = Vec::new();
+        let frame_count = renderer.render(&bass_burst(), &mut out).unwrap();
+        assert_eq!(frame_count, 30);
+
+        let rows: Vec<Vec<f32>> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .skip(1)
+            .map(|line| line.split(',').map(|v| v.parse().unwrap()).collect())
+            .collect();
+        assert_eq!(rows.len(), frame_count);
+        assert_eq!(rows[0].len(), 1 + PIXEL_COUNT * 3);
+
+        // Green is the default accent color
+        assert_eq!(rows[5][2], 0.0);
+        assert!(rows[20][2] > 100.0);
+    }
+
+    #[test]
+    fn renders_are_reproducible() {
+        let render = || {
+            let mut renderer = renderer(Mode::Thunderstruck, RenderFormat::JsonLines, 42);
+            let mut out = Vec::new();
+            renderer.render(&bass_burst(), &mut out).unwrap();
+            out
+        };
+
+        assert_eq!(render(), render());
+    }
+}
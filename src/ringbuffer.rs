@@ -0,0 +1,237 @@
+use std::sync::atomic::{fence, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Lock-free single-producer ring of audio frames, one lane per channel.
+/// Samples are stored as f32 bits in atomics, so a reader racing the writer
+/// sees stale or fresh samples, never undefined behavior. Overwritten
+/// samples are detected like in a seqlock: the writer announces how far it
+/// is about to write before storing any samples.
+pub struct SampleRing {
+    lanes: Vec<Box<[AtomicU32]>>,
+    mask: usize,
+    /// Total number of frames ever written, wrapping
+    write_pos: AtomicUsize,
+    /// Where the block being written ends, ahead of `write_pos` while the
+    /// writer stores samples
+    write_end: AtomicUsize,
+}
+
+impl SampleRing {
+    /// The capacity is rounded up to the next power of two
+    pub fn new(channel_count: usize, capacity: usize) -> SampleRing {
+        let capacity = capacity.next_power_of_two();
+        let lanes = (0..channel_count.max(1))
+            .map(|_| (0..capacity).map(|_| AtomicU32::new(0)).collect())
+            .collect();
+
+        SampleRing {
+            lanes,
+            mask: capacity - 1,
+            write_pos: AtomicUsize::new(0),
+            write_end: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn channel_count(&self) -> usize {
+        self.lanes.len()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.mask + 1
+    }
+
+    fn write_pos(&self) -> usize {
+        self.write_pos.load(Ordering::Acquire)
+    }
+
+    /// Appends one block per channel, e.g. `&[&[f32]]` or `&[Vec<f32>]`.
+    /// Missing channels are written as silence, samples beyond the shortest
+    /// block are dropped. Must only be called from one thread at a time.
+    pub fn push<B: AsRef<[f32]>>(&self, channel_blocks: &[B]) {
+        let frame_count = channel_blocks
+            .iter()
+            .map(|block| block.as_ref().len())
+            .min()
+            .unwrap_or(0);
+        let start = self.write_pos.load(Ordering::Relaxed);
+        let end = start.wrapping_add(frame_count);
+        // Readers seeing any of the samples below also see this
+        self.write_end.store(end, Ordering::Relaxed);
+        fence(Ordering::Release);
+
+        for (channel, lane) in self.lanes.iter().enumerate() {
+            let block = channel_blocks.get(channel).map(AsRef::as_ref);
+            for i in 0..frame_count {
+                let sample = block.map_or(0.0, |block| block[i]);
+                lane[start.wrapping_add(i) & self.mask].store(sample.to_bits(), Ordering::Relaxed);
+            }
+        }
+
+        // Publishes the samples above to readers
+        self.write_pos.store(end, Ordering::Release);
+    }
+
+    /// Whether samples from `pos` on may have been overwritten since they
+    /// were read. Call after reading them.
+    fn overwritten_since(&self, pos: usize) -> bool {
+        fence(Ordering::Acquire);
+        self.write_end.load(Ordering::Relaxed).wrapping_sub(pos) > self.capacity()
+    }
+
+    fn read(&self, channel: usize, pos: usize, out: &mut [f32]) {
+        let lane = &self.lanes[channel];
+        for (i, sample) in out.iter_mut().enumerate() {
+            *sample = f32::from_bits(lane[pos.wrapping_add(i) & self.mask].load(Ordering::Relaxed));
+        }
+    }
+}
+
+/// Reads a `SampleRing` in hops, keeping a sliding analysis window per
+/// channel. Consecutive windows overlap by `window_size - hop_size` samples.
+pub struct RingReader {
+    ring: Arc<SampleRing>,
+    read_pos: usize,
+    hop_size: usize,
+    hop: Vec<f32>,
+    windows: Vec<Vec<f32>>,
+    mid: Vec<f32>,
+}
+
+impl RingReader {
+    /// Starts reading at the ring's current write position
+    pub fn new(ring: Arc<SampleRing>, window_size: usize, hop_size: usize) -> RingReader {
+        let hop_size = hop_size.clamp(1, window_size);
+        RingReader {
+            read_pos: ring.write_pos(),
+            windows: vec![vec![0.0; window_size]; ring.channel_count()],
+            ring,
+            hop_size,
+            hop: vec![0.0; hop_size],
+            mid: vec![0.0; window_size],
+        }
+    }
+
+    pub fn ring(&self) -> &Arc<SampleRing> {
+        &self.ring
+    }
+
+    /// Advances all windows by one hop. Returns false if there isn't a full
+    /// hop of new samples yet.
+    pub fn next_hop(&mut self) -> bool {
+        self.next_hop_with(&mut |_, _, _| {})
+    }
+
+    /// Like `next_hop`, but runs each channel's new samples through `process`
+    /// before they enter the window. Besides channel and samples, `process`
+    /// learns whether samples were skipped before these, making any state it
+    /// kept from earlier hops stale.
+    pub fn next_hop_with(&mut self, process: &mut dyn FnMut(usize, &mut [f32], bool)) -> bool {
+        self.read_hop(process, false)
+    }
+
+    fn read_hop(
+        &mut self,
+        process: &mut dyn FnMut(usize, &mut [f32], bool),
+        skipped: bool,
+    ) -> bool {
+        let mut skipped = skipped;
+        let write_pos = self.ring.write_pos();
+        let mut available = write_pos.wrapping_sub(self.read_pos);
+        // Fell too far behind, the oldest samples are about to be overwritten
+        if available > self.ring.capacity() / 2 {
+            log::debug!("Analysis fell behind, skipping {available} frames");
+            self.read_pos = write_pos.wrapping_sub(self.hop_size);
+            available = self.hop_size;
+            skipped = true;
+        }
+        if available < self.hop_size {
+            return false;
+        }
+
+        let window_size = self.mid.len();
+        for (channel, window) in self.windows.iter_mut().enumerate() {
+            self.ring.read(channel, self.read_pos, &mut self.hop);
+            process(channel, &mut self.hop, skipped);
+            window.copy_within(self.hop_size.., 0);
+            window[window_size - self.hop_size..].copy_from_slice(&self.hop);
+        }
+
+        // The writer lapped us while copying, the hop may be torn
+        let lapped = self.ring.overwritten_since(self.read_pos);
+        self.read_pos = self.read_pos.wrapping_add(self.hop_size);
+        if lapped {
+            log::debug!("Analysis window was overwritten while reading");
+            return self.read_hop(process, true);
+        }
+
+        let channel_count = self.windows.len() as f32;
+        for (i, sample) in self.mid.iter_mut().enumerate() {
+            *sample = self.windows.iter().map(|w| w[i]).sum::<f32>() / channel_count;
+        }
+        true
+    }
+
+    /// The current window of every channel
+    pub fn windows(&self) -> &[Vec<f32>] {
+        &self.windows
+    }
+
+    /// The current window averaged over all channels
+    pub fn mid(&self) -> &[f32] {
+        &self.mid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_overlapping_hops() {
+        let ring = Arc::new(SampleRing::new(2, 8));
+        let mut reader = RingReader::new(Arc::clone(&ring), 4, 2);
+        assert!(!reader.next_hop());
+
+        ring.push(&[vec![1.0, 2.0, 3.0], vec![-1.0, 0.0, 1.0]]);
+        assert!(reader.next_hop());
+        assert!(!reader.next_hop());
+        assert_eq!(reader.windows()[0], vec![0.0, 0.0, 1.0, 2.0]);
+        assert_eq!(reader.mid(), &[0.0, 0.0, 0.0, 1.0]);
+
+        // Wraps around the end of the ring
+        ring.push(&[vec![4.0, 5.0, 6.0], vec![0.0; 3]]);
+        assert!(reader.next_hop());
+        assert!(reader.next_hop());
+        assert_eq!(reader.windows()[0], vec![3.0, 4.0, 5.0, 6.0]);
+    }
+
+    #[test]
+    fn skips_ahead_after_overrun() {
+        let ring = Arc::new(SampleRing::new(1, 8));
+        let mut reader = RingReader::new(Arc::clone(&ring), 4, 2);
+
+        let block: Vec<f32> = (0..20).map(|v| v as f32).collect();
+        ring.push(&[block]);
+        let mut skips = vec![];
+        assert!(reader.next_hop_with(&mut |_, _, skipped| skips.push(skipped)));
+        assert_eq!(reader.windows()[0], vec![0.0, 0.0, 18.0, 19.0]);
+        assert!(!reader.next_hop());
+
+        ring.push(&[vec![20.0, 21.0]]);
+        assert!(reader.next_hop_with(&mut |_, _, skipped| skips.push(skipped)));
+        assert_eq!(skips, [true, false]);
+    }
+
+    #[test]
+    fn drops_hops_overwritten_by_a_block_in_progress() {
+        let ring = Arc::new(SampleRing::new(1, 8));
+        let mut reader = RingReader::new(Arc::clone(&ring), 4, 2);
+        ring.push(&[[1.0, 2.0].as_slice()]);
+
+        // A writer announced a block wrapping all the way around, but hasn't
+        // published it yet
+        ring.write_end
+            .store(ring.write_pos() + 8, Ordering::Relaxed);
+        assert!(!reader.next_hop());
+    }
+}
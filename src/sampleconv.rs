@@ -0,0 +1,177 @@
+use serde::Deserialize;
+
+use crate::wavdecoder::i16_to_f32;
+
+/// Raw interleaved PCM sample formats
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum PcmFormat {
+    S16le,
+    F32le,
+}
+
+impl PcmFormat {
+    pub fn sample_size(&self) -> usize {
+        match self {
+            PcmFormat::S16le => 2,
+            PcmFormat::F32le => 4,
+        }
+    }
+
+    /// Appends the decoded samples to `out`, ignoring trailing partial samples.
+    pub fn decode(&self, bytes: &[u8], out: &mut Vec<f32>) {
+        match self {
+            PcmFormat::S16le => out.extend(
+                bytes
+                    .chunks_exact(2)
+                    .map(|b| i16_to_f32(i16::from_le_bytes([b[0], b[1]]))),
+            ),
+            PcmFormat::F32le => out.extend(
+                bytes
+                    .chunks_exact(4)
+                    .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+            ),
+        }
+    }
+}
+
+/// Splits an interleaved buffer into one buffer per channel, dropping a
+/// trailing partial frame.
+pub fn deinterleave(samples: &[f32], channels: usize) -> Vec<Vec<f32>> {
+    let mut out = vec![Vec::with_capacity(samples.len() / channels); channels];
+    for frame in samples.chunks_exact(channels) {
+        for (buffer, sample) in out.iter_mut().zip(frame) {
+            buffer.push(*sample);
+        }
+    }
+
+    out
+}
+
+/// Linear interpolation resampler for mono buffers. Good enough for analysis,
+/// not meant for playback.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32) -> Vec<f32> {
+    if from_rate == to_rate || samples.is_empty() {
+        return samples.to_vec();
+    }
+
+    let step = from_rate as f64 / to_rate as f64;
+    let out_len = (samples.len() as f64 / step) as usize;
+    let mut out = Vec::with_capacity(out_len);
+    for i in 0..out_len {
+        let pos = i as f64 * step;
+        let index = pos as usize;
+        let frac = (pos - index as f64) as f32;
+        let cur = samples[index];
+        let next = samples.get(index + 1).copied().unwrap_or(cur);
+        out.push(cur + (next - cur) * frac);
+    }
+
+    out
+}
+
+/// Linear interpolation resampler for streams that arrive in blocks. Keeps
+/// its position across blocks, so block boundaries don't cause drift or
+/// discontinuities.
+pub struct Resampler {
+    step: f64,
+    /// Position of the next output sample relative to the current block. In
+    /// [-1; 0) it lies between the previous block's last sample and this one.
+    pos: f64,
+    last: f32,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32) -> Resampler {
+        Resampler {
+            step: from_rate as f64 / to_rate as f64,
+            pos: 0.0,
+            last: 0.0,
+        }
+    }
+
+    /// Appends the resampled block to `out`
+    pub fn process(&mut self, samples: &[f32], out: &mut Vec<f32>) {
+        if self.step == 1.0 {
+            out.extend_from_slice(samples);
+            return;
+        }
+
+        // Interpolating past the last sample has to wait for the next block
+        while self.pos < samples.len() as f64 - 1.0 {
+            let index = self.pos.floor();
+            let frac = (self.pos - index) as f32;
+            let (cur, next) = if index < 0.0 {
+                (self.last, samples[0])
+            } else {
+                (samples[index as usize], samples[index as usize + 1])
+            };
+            out.push(cur + (next - cur) * frac);
+            self.pos += self.step;
+        }
+
+        if let Some(last) = samples.last() {
+            self.last = *last;
+            self.pos -= samples.len() as f64;
+        }
+    }
+}
+
+/// Splits an interleaved block into channels and runs each through its own
+/// resampler.
+pub fn deinterleave_resampled(samples: &[f32], resamplers: &mut [Resampler]) -> Vec<Vec<f32>> {
+    deinterleave(samples, resamplers.len())
+        .iter()
+        .zip(resamplers)
+        .map(|(channel, resampler)| {
+            let mut out = Vec::with_capacity(channel.len());
+            resampler.process(channel, &mut out);
+            out
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_raw_pcm() {
+        let mut out = Vec::new();
+        PcmFormat::S16le.decode(&[0xFF, 0x7F, 0x00, 0x00, 0x01], &mut out);
+        PcmFormat::F32le.decode(&(-0.5f32).to_le_bytes(), &mut out);
+        assert_eq!(out, vec![1.0, 0.0, -0.5]);
+    }
+
+    #[test]
+    fn deinterleave_splits_channels() {
+        assert_eq!(
+            deinterleave(&[1.0, 0.0, -0.5, -0.5, 0.25], 2),
+            vec![vec![1.0, -0.5], vec![0.0, -0.5]]
+        );
+    }
+
+    #[test]
+    fn resample_interpolates() {
+        assert_eq!(resample(&[0.0, 1.0, 0.0, -1.0], 2, 4).len(), 8);
+        assert_eq!(resample(&[0.0, 1.0], 1, 2), vec![0.0, 0.5, 1.0, 1.0]);
+        assert_eq!(resample(&[0.0, 1.0, 2.0, 3.0], 2, 1), vec![0.0, 2.0]);
+    }
+
+    #[test]
+    fn resampler_continues_across_blocks() {
+        let mut resampler = Resampler::new(1, 2);
+        let mut out = Vec::new();
+        resampler.process(&[0.0, 1.0], &mut out);
+        resampler.process(&[], &mut out);
+        resampler.process(&[2.0, 3.0], &mut out);
+        assert_eq!(out, vec![0.0, 0.5, 1.0, 1.5, 2.0, 2.5]);
+
+        let mut resampler = Resampler::new(3, 2);
+        let mut out = Vec::new();
+        for block in [0.0, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0].chunks(2) {
+            resampler.process(block, &mut out);
+        }
+        assert_eq!(out, vec![0.0, 1.5, 3.0, 4.5]);
+    }
+}
@@ -1,171 +1,287 @@
 extern crate sdl2;
 
-use log::error;
 use sdl2::audio::AudioStatus::Playing;
 use sdl2::{audio::*, AudioSubsystem, Sdl};
-use std::process;
+use std::path::{Path, PathBuf};
 use std::{
     sync::{Arc, Mutex},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
+use crate::audiofile::{self, DecodedAudio};
 use crate::audiosource::AudioSource;
-use crate::playbackstate::PlaybackState;
-
-fn u8_to_i16(v: &[u8]) -> &[i16] {
-    unsafe { std::slice::from_raw_parts(v.as_ptr() as *const i16, v.len() / 2) }
-}
-
-fn i16_to_f32(v: &[i16]) -> Vec<f32> {
-    v.iter()
-        .map(|&sample| sample as f32 / i16::MAX as f32)
-        .collect()
-}
+use crate::osc::OscSender;
+use crate::playbackstate::{PlaybackState, Skip, Transport};
+use crate::ringbuffer::SampleRing;
+use crate::sampleconv;
 
 struct WavFileCallback {
-    samples: Vec<i16>,
-    analysis_buffer: Vec<f32>,
+    samples: Vec<f32>,
+    channels: usize,
+    sample_rate: u32,
+    analysis_buffers: Vec<Vec<f32>>,
+    analysis_ratio: f64,
     file_pos: usize,
-    playback_state: Arc<Mutex<PlaybackState>>,
+    finished: bool,
+    ring: Arc<SampleRing>,
 }
 
 impl WavFileCallback {
-    fn new(samples: Vec<i16>, playback_state: Arc<Mutex<PlaybackState>>) -> WavFileCallback {
-        let analysis_buffer = WavFileCallback::convert_audio_buffer(&samples);
+    fn new(audio: DecodedAudio, analysis_rate: u32, ring: Arc<SampleRing>) -> WavFileCallback {
+        let analysis_buffers = WavFileCallback::convert_audio_buffer(&audio, analysis_rate);
 
         WavFileCallback {
-            samples,
-            analysis_buffer,
+            samples: audio.samples,
+            channels: audio.channels as usize,
+            sample_rate: audio.sample_rate,
+            analysis_buffers,
+            analysis_ratio: analysis_rate as f64 / audio.sample_rate as f64,
             file_pos: 0,
-            playback_state,
+            finished: false,
+            ring,
         }
     }
 
-    fn convert_audio_buffer(samples: &[i16]) -> Vec<f32> {
-        let analysis_buffer = i16_to_f32(samples);
-
-        let _file_max = samples
-            .iter()
-            .reduce(|a, b| if a >= b { a } else { b })
-            .expect("");
-        let _file_min = samples
+    /// Playback uses the file as-is, analysis wants separate channels at the
+    /// analysis rate.
+    fn convert_audio_buffer(audio: &DecodedAudio, analysis_rate: u32) -> Vec<Vec<f32>> {
+        sampleconv::deinterleave(&audio.samples, audio.channels as usize)
             .iter()
-            .reduce(|a, b| if a < b { a } else { b })
-            .expect("");
-        let _ana_min = analysis_buffer
-            .iter()
-            .reduce(|a, b| if a < b { a } else { b })
-            .expect("D'oh.");
-        let _ana_max = analysis_buffer
-            .iter()
-            .reduce(|a, b| if a >= b { a } else { b })
-            .expect("D'oh.");
-        //println!("Sample extrema of input file: {}/{}", _file_min, _file_max);
-        //println!("Sample extrema for analysis: {}/{}", _ana_min, _ana_max);
+            .map(|channel| sampleconv::resample(channel, audio.sample_rate, analysis_rate))
+            .collect()
+    }
 
-        return analysis_buffer;
+    fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels
     }
-}
 
-// Needed because AudioSpecWAV does not implement Send by itself
-// https://github.com/Rust-SDL2/rust-sdl2/issues/1108
-unsafe impl Send for WavFileCallback {}
+    /// Jumps to a fraction of the track
+    fn seek(&mut self, progress: f32) {
+        self.file_pos = (self.frame_count() as f32 * progress.clamp(0.0, 1.0)) as usize;
+        self.finished = false;
+    }
+}
 
 impl AudioCallback for WavFileCallback {
-    type Channel = i16;
-
-    fn callback(&mut self, out: &mut [i16]) {
-        let mut playback_state = self.playback_state.lock().unwrap();
+    type Channel = f32;
 
-        for i in 0..out.len() {
-            out[i] = self.samples[self.file_pos + i];
+    fn callback(&mut self, out: &mut [f32]) {
+        let sample_pos = (self.file_pos * self.channels).min(self.samples.len());
+        let available = (self.samples.len() - sample_pos).min(out.len());
+        out[..available].copy_from_slice(&self.samples[sample_pos..sample_pos + available]);
+        // Play silence past the end until the player closes the device
+        if available < out.len() {
+            out[available..].fill(0.0);
+            self.finished = true;
         }
 
-        let window_size = playback_state.buffer.capacity();
-        let window_end = self.file_pos + window_size;
-        if window_end < self.analysis_buffer.len() {
-            (*playback_state).buffer = self.analysis_buffer[self.file_pos..window_end].to_vec();
-        }
+        // Hand the analysis exactly what is being played
+        let frame_count = available / self.channels;
+        let analysis_len = self.analysis_buffers[0].len();
+        let block_start = ((self.file_pos as f64 * self.analysis_ratio) as usize).min(analysis_len);
+        let block_end = (((self.file_pos + frame_count) as f64 * self.analysis_ratio) as usize)
+            .min(analysis_len);
+        let blocks: Vec<&[f32]> = self
+            .analysis_buffers
+            .iter()
+            .map(|channel| &channel[block_start..block_end])
+            .collect();
+        self.ring.push(&blocks);
 
-        self.file_pos += out.len();
+        self.file_pos += frame_count;
     }
 }
 
+enum TrackEnd {
+    Finished,
+    Skipped(Skip),
+    Stopped,
+}
+
+/// Plays a playlist of sound files, remote controlled through
+/// `PlaybackState::transport`.
 pub struct SDLPlayer {
     _sdl_context: Sdl,
-    _sdl_audio: AudioSubsystem,
-    device: AudioDevice<WavFileCallback>,
+    sdl_audio: AudioSubsystem,
     playback_state: Arc<Mutex<PlaybackState>>,
+    tracks: Vec<PathBuf>,
+    sample_rate: Option<u32>,
+    osc: OscSender,
 }
 
 impl SDLPlayer {
-    fn get_samples_from_file(file_path: &str) -> Vec<i16> {
-        let wav_file_spec =
-            AudioSpecWAV::load_wav(file_path).expect("Cannot load wav file: {error_msg}");
-
-        if wav_file_spec.channels != 1
-            || wav_file_spec.format != AudioFormat::S16LSB
-            || wav_file_spec.freq != 44100
-        {
-            error!("WAV file needs to be s16le, 44100 kHz, mono.");
-            process::exit(1);
+    /// Analyzes at each file's sample rate unless a different one is requested
+    pub fn new(
+        tracks: Vec<PathBuf>,
+        playback_state: Arc<Mutex<PlaybackState>>,
+        sample_rate: Option<u32>,
+        looping: bool,
+        osc: OscSender,
+    ) -> Result<SDLPlayer, String> {
+        if let Some(missing) = tracks.iter().find(|track| !track.is_file()) {
+            return Err(format!("Cannot find sound file {}", missing.display()));
         }
 
-        u8_to_i16(wav_file_spec.buffer()).to_vec()
-    }
-
-    pub fn new(file_path: &str, playback_state: Arc<Mutex<PlaybackState>>) -> SDLPlayer {
         let sdl_context = sdl2::init().expect("Cannot initialize SDL2 🤷‍♀️");
         let sdl_audio = match sdl_context.audio() {
             Ok(audio) => audio,
-            Err(msg) => {
-                error!("Cannot init SDL audio: {msg}");
-                process::exit(1);
+            Err(msg) => return Err(format!("Cannot init SDL audio: {msg}")),
+        };
+        // Start over instead of inheriting requests made to a previous player
+        playback_state.lock().unwrap().transport = Transport {
+            looping,
+            ..Transport::default()
+        };
+
+        Ok(SDLPlayer {
+            _sdl_context: sdl_context,
+            sdl_audio,
+            playback_state,
+            tracks,
+            sample_rate,
+            osc,
+        })
+    }
+
+    fn open_track(&self, path: &Path) -> Result<AudioDevice<WavFileCallback>, String> {
+        let audio = audiofile::load(&path.to_string_lossy())?;
+        let channels = match u8::try_from(audio.channels) {
+            Ok(channels) => channels,
+            Err(_) => {
+                return Err(format!(
+                    "Cannot play {} channels, at most 255 are supported",
+                    audio.channels
+                ))
             }
         };
-        let samples = SDLPlayer::get_samples_from_file(file_path);
+        let analysis_rate = self.sample_rate.unwrap_or(audio.sample_rate);
+        let ring = {
+            let mut playback_state = self.playback_state.lock().unwrap();
+            playback_state.sample_rate = analysis_rate;
+            playback_state.open_ring(audio.channels as usize)
+        };
+
         let desired_spec = AudioSpecDesired {
-            freq: Some(44100),
-            channels: Some(1),
+            freq: Some(audio.sample_rate as i32),
+            channels: Some(channels),
             samples: None, // Default sample buffer size
         };
 
-        let device = match sdl_audio.open_playback(None, &desired_spec, |spec| {
-            if spec.freq != desired_spec.freq.expect("No desired freq?!")
-                || spec.channels != desired_spec.channels.expect("No desired channel count?!")
-            {
-                error!("Actual AudioSpec does not match desired spec.");
-                process::exit(1);
-            }
-
-            WavFileCallback::new(samples, Arc::clone(&playback_state))
+        let device = match self.sdl_audio.open_playback(None, &desired_spec, |_| {
+            WavFileCallback::new(audio, analysis_rate, ring)
         }) {
             Ok(device) => device,
-            Err(msg) => {
-                error!("Cannot open audio device: {msg}");
-                process::exit(1);
-            }
+            Err(msg) => return Err(format!("Cannot open audio device: {msg}")),
         };
 
-        SDLPlayer {
-            _sdl_context: sdl_context,
-            _sdl_audio: sdl_audio,
-            device,
-            playback_state,
+        if Some(device.spec().freq) != desired_spec.freq
+            || Some(device.spec().channels) != desired_spec.channels
+        {
+            return Err("Actual AudioSpec does not match desired spec.".to_string());
+        }
+
+        Ok(device)
+    }
+
+    fn play_track(&mut self, path: &Path) -> Result<TrackEnd, String> {
+        let mut device = self.open_track(path)?;
+        let (frame_count, sample_rate) = {
+            let callback = device.lock();
+            (callback.frame_count(), callback.sample_rate)
+        };
+
+        let track_name = match path.file_stem() {
+            Some(stem) => stem.to_string_lossy().to_string(),
+            None => path.display().to_string(),
+        };
+        log::info!("Playing {}", path.display());
+        self.osc.send_track(&track_name);
+        let mut reported = Instant::now();
+
+        loop {
+            let transport = {
+                let mut playback_state = self.playback_state.lock().unwrap();
+                if playback_state.should_stop() {
+                    return Ok(TrackEnd::Stopped);
+                }
+
+                let transport = playback_state.transport.clone();
+                // Requests are handled exactly once
+                playback_state.transport.seek = None;
+                playback_state.transport.skip = None;
+                transport
+            };
+
+            if let Some(skip) = transport.skip {
+                return Ok(TrackEnd::Skipped(skip));
+            }
+
+            let playing = device.status() == Playing;
+            if transport.paused && playing {
+                device.pause();
+            } else if !transport.paused && !playing {
+                device.resume();
+            }
+
+            let (file_pos, finished) = {
+                let mut callback = device.lock();
+                if let Some(progress) = transport.seek {
+                    callback.seek(progress);
+                }
+                (callback.file_pos, callback.finished)
+            };
+            if finished {
+                return Ok(TrackEnd::Finished);
+            }
+
+            // Don't spam the network, a few updates per second keep displays current
+            if reported.elapsed() > Duration::from_millis(250) {
+                self.osc.send_position(file_pos as f32 / sample_rate as f32);
+                self.osc
+                    .send_progress(file_pos as f32 / frame_count.max(1) as f32);
+                self.osc.send_paused(transport.paused);
+                self.osc.send_looping(transport.looping);
+                reported = Instant::now();
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
         }
     }
 }
 
 impl AudioSource for SDLPlayer {
     fn run(&mut self) {
-        self.device.resume();
+        let mut index = 0;
+        let mut failures = 0;
 
-        while self.device.status() == Playing {
-            std::thread::sleep(Duration::from_millis(50));
+        while index < self.tracks.len() {
+            let track = self.tracks[index].clone();
+            let end = match self.play_track(&track) {
+                Ok(end) => {
+                    failures = 0;
+                    end
+                }
+                Err(err) => {
+                    log::error!("Cannot play {}: {err}", track.display());
+                    failures += 1;
+                    if failures == self.tracks.len() {
+                        break;
+                    }
+                    TrackEnd::Finished
+                }
+            };
+
+            index = match end {
+                TrackEnd::Finished | TrackEnd::Skipped(Skip::Next) => index + 1,
+                TrackEnd::Skipped(Skip::Previous) => index.saturating_sub(1),
+                TrackEnd::Stopped => return,
+            };
 
-            if self.playback_state.lock().unwrap().shutdown {
-                break;
+            if index == self.tracks.len() && self.playback_state.lock().unwrap().transport.looping {
+                index = 0;
             }
         }
+
+        log::info!("End of playlist");
     }
 }
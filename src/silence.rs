@@ -0,0 +1,111 @@
+use serde::Deserialize;
+
+/// What the lights do while the music is paused
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdleAction {
+    /// Show the accent color
+    Static,
+    /// Slowly fade the accent color in and out
+    Breathe,
+    /// Switch the lights off
+    Off,
+}
+
+/// Settings of the silence detection and what to do during silence
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct IdleConfig {
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// RMS level in dBFS below which the input counts as silent
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    /// Seconds of silence before going idle
+    #[serde(default = "default_hold")]
+    pub hold: f32,
+    #[serde(default = "default_action")]
+    pub action: IdleAction,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_threshold() -> f32 {
+    -60.0
+}
+
+fn default_hold() -> f32 {
+    10.0
+}
+
+fn default_action() -> IdleAction {
+    IdleAction::Breathe
+}
+
+impl Default for IdleConfig {
+    /// Disabled, the effects keep running during silence
+    fn default() -> Self {
+        IdleConfig {
+            enabled: false,
+            threshold: default_threshold(),
+            hold: default_hold(),
+            action: default_action(),
+        }
+    }
+}
+
+/// Tells whether the input has been silent for the configured hold time.
+/// Any frame above the threshold ends the silence right away.
+#[derive(Default)]
+pub struct SilenceDetector {
+    silent_for: f32,
+}
+
+impl SilenceDetector {
+    /// Takes the RMS level of a frame `elapsed` seconds after the previous
+    /// one, returns whether the input is idle
+    pub fn process(&mut self, config: &IdleConfig, rms: f32, elapsed: f32) -> bool {
+        let level = 20.0 * rms.max(1e-10).log10();
+        if config.enabled && level < config.threshold {
+            self.silent_for += elapsed;
+        } else {
+            self.silent_for = 0.0;
+        }
+
+        config.enabled && self.silent_for >= config.hold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn idles_after_hold_time() {
+        let config = IdleConfig {
+            enabled: true,
+            hold: 1.0,
+            ..IdleConfig::default()
+        };
+        let mut detector = SilenceDetector::default();
+        // Sums up exactly
+        let frame = 0.25;
+
+        for _ in 0..3 {
+            assert!(!detector.process(&config, 0.0001, frame));
+        }
+        // A short burst starts the hold time over
+        assert!(!detector.process(&config, 0.1, frame));
+        for _ in 0..3 {
+            assert!(!detector.process(&config, 0.0, frame));
+        }
+        assert!(detector.process(&config, 0.0, frame));
+        assert!(!detector.process(&config, 0.01, frame));
+
+        let mut detector = SilenceDetector::default();
+        for _ in 0..8 {
+            assert!(!detector.process(&IdleConfig::default(), 0.0, frame));
+        }
+    }
+}
@@ -0,0 +1,364 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde::Deserialize;
+
+use crate::alsainput::AlsaInput;
+use crate::audiosource::AudioSource;
+use crate::generator::{GeneratorInput, Signal};
+#[cfg(feature = "jack")]
+use crate::jackinput::JackInput;
+use crate::mixinput::MixInput;
+use crate::netinput::{NetFormat, NetInput};
+use crate::osc::OscSender;
+use crate::playbackstate::{PlaybackState, DEFAULT_SAMPLE_RATE};
+use crate::pulseinput::PulseInput;
+use crate::sampleconv::PcmFormat;
+use crate::sdlplayer::SDLPlayer;
+use crate::stdininput::StdinInput;
+
+/// One audio source. Exactly one of `pa_device`, `alsa_device`,
+/// `jack_client_name`, `sound_file_path`, `stdin_format`, `net_listen_addr`,
+/// `generator` and `mix` selects its kind, the other fields refine it.
+#[derive(Clone, Default, Deserialize)]
+pub struct SourceConfig {
+    pub pa_device: Option<String>,
+    pub pa_channels: Option<u16>,
+    pub alsa_device: Option<String>,
+    pub alsa_channels: Option<u16>,
+    /// In frames
+    pub alsa_period_size: Option<usize>,
+    /// In frames
+    pub alsa_buffer_size: Option<usize>,
+    pub jack_client_name: Option<String>,
+    /// Input ports to register, one per channel
+    pub jack_ports: Option<Vec<String>>,
+    /// Output ports to connect to the input ports, in the same order
+    pub jack_connect: Option<Vec<String>>,
+    pub sound_file_path: Option<PathBuf>,
+    /// Files to play after `sound_file_path`
+    pub playlist: Option<Vec<PathBuf>>,
+    pub loop_playback: Option<bool>,
+    pub stdin_format: Option<PcmFormat>,
+    pub stdin_sample_rate: Option<u32>,
+    pub stdin_channels: Option<u16>,
+    pub net_listen_addr: Option<String>,
+    pub net_format: Option<NetFormat>,
+    pub net_sample_rate: Option<u32>,
+    pub net_channels: Option<u16>,
+    pub generator: Option<Signal>,
+    pub generator_level: Option<f32>,
+    /// Names of other sources to mix
+    pub mix: Option<Vec<String>>,
+    /// Amplification of this source when mixed
+    pub gain: Option<f32>,
+}
+
+impl SourceConfig {
+    /// Number of source kinds given, valid sources have exactly one
+    pub fn kind_count(&self) -> usize {
+        [
+            self.pa_device.is_some(),
+            self.alsa_device.is_some(),
+            self.jack_client_name.is_some(),
+            self.sound_file_path.is_some(),
+            self.stdin_format.is_some(),
+            self.net_listen_addr.is_some(),
+            self.generator.is_some(),
+            self.mix.is_some(),
+        ]
+        .iter()
+        .filter(|given| **given)
+        .count()
+    }
+
+    /// Splits a source with several kinds into one source per kind
+    pub fn split_kinds(&self) -> Vec<(&'static str, SourceConfig)> {
+        let base = SourceConfig {
+            pa_device: None,
+            alsa_device: None,
+            jack_client_name: None,
+            sound_file_path: None,
+            stdin_format: None,
+            net_listen_addr: None,
+            generator: None,
+            mix: None,
+            ..self.clone()
+        };
+
+        let mut parts = Vec::new();
+        if self.pa_device.is_some() {
+            parts.push((
+                "pulse",
+                SourceConfig {
+                    pa_device: self.pa_device.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.alsa_device.is_some() {
+            parts.push((
+                "alsa",
+                SourceConfig {
+                    alsa_device: self.alsa_device.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.jack_client_name.is_some() {
+            parts.push((
+                "jack",
+                SourceConfig {
+                    jack_client_name: self.jack_client_name.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.sound_file_path.is_some() {
+            parts.push((
+                "file",
+                SourceConfig {
+                    sound_file_path: self.sound_file_path.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.stdin_format.is_some() {
+            parts.push((
+                "stdin",
+                SourceConfig {
+                    stdin_format: self.stdin_format,
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.net_listen_addr.is_some() {
+            parts.push((
+                "net",
+                SourceConfig {
+                    net_listen_addr: self.net_listen_addr.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        if self.generator.is_some() {
+            parts.push((
+                "generator",
+                SourceConfig {
+                    generator: self.generator.clone(),
+                    ..base.clone()
+                },
+            ));
+        }
+        parts
+    }
+
+    /// Mixes need the other sources and are created by `SourceManager`
+    pub fn create(
+        &self,
+        playback_state: Arc<Mutex<PlaybackState>>,
+        sample_rate: Option<u32>,
+        osc_dst_addr: &str,
+    ) -> Result<Box<dyn AudioSource>, String> {
+        if let Some(sound_file_path) = self.sound_file_path.as_ref() {
+            let mut tracks = vec![sound_file_path.clone()];
+            tracks.extend(self.playlist.iter().flatten().cloned());
+            // The player reports its position separately from the photonizer
+            let osc_sender = OscSender::new(&osc_dst_addr)?;
+            return Ok(Box::new(SDLPlayer::new(
+                tracks,
+                Arc::clone(&playback_state),
+                sample_rate,
+                self.loop_playback.unwrap_or(false),
+                osc_sender,
+            )?));
+        };
+
+        if let Some(stdin_format) = self.stdin_format {
+            return Ok(Box::new(StdinInput::new(
+                Arc::clone(&playback_state),
+                stdin_format,
+                self.stdin_sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+                self.stdin_channels.unwrap_or(1),
+                sample_rate,
+            )));
+        }
+
+        if let Some(net_listen_addr) = self.net_listen_addr.as_deref() {
+            return Ok(Box::new(NetInput::new(
+                Arc::clone(&playback_state),
+                &net_listen_addr,
+                self.net_format.unwrap_or(NetFormat::RtpL16),
+                self.net_sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+                self.net_channels.unwrap_or(1),
+                sample_rate,
+            )?));
+        }
+
+        if let Some(signal) = self.generator.as_ref() {
+            return Ok(Box::new(GeneratorInput::new(
+                Arc::clone(&playback_state),
+                signal.clone(),
+                self.generator_level.unwrap_or(0.5),
+                sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+            )));
+        }
+
+        if let Some(pa_device) = self.pa_device.as_deref() {
+            return Ok(Box::new(PulseInput::new(
+                Arc::clone(&playback_state),
+                pa_device,
+                sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+                self.pa_channels.unwrap_or(2),
+            )?));
+        }
+
+        if let Some(alsa_device) = self.alsa_device.as_deref() {
+            return Ok(Box::new(AlsaInput::new(
+                Arc::clone(&playback_state),
+                alsa_device,
+                sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+                self.alsa_channels.unwrap_or(2),
+                self.alsa_period_size,
+                self.alsa_buffer_size,
+            )?));
+        }
+
+        #[cfg(feature = "jack")]
+        if let Some(jack_client_name) = self.jack_client_name.as_deref() {
+            let default_ports = vec!["left".to_string(), "right".to_string()];
+            return Ok(Box::new(JackInput::new(
+                Arc::clone(&playback_state),
+                jack_client_name,
+                self.jack_ports.as_ref().unwrap_or(&default_ports),
+                self.jack_connect.as_deref().unwrap_or_default(),
+            )?));
+        }
+
+        #[cfg(not(feature = "jack"))]
+        if self.jack_client_name.is_some() {
+            return Err("Built without JACK support, enable the \"jack\" feature".to_string());
+        }
+
+        Err("No audio source given".to_string())
+    }
+}
+
+/// Runs the active audio source and switches to another one when requested
+/// through `PlaybackState::request_source`.
+pub struct SourceManager {
+    sources: BTreeMap<String, SourceConfig>,
+    playback_state: Arc<Mutex<PlaybackState>>,
+    sample_rate: Option<u32>,
+    osc_dst_addr: String,
+}
+
+impl SourceManager {
+    pub fn new(
+        sources: BTreeMap<String, SourceConfig>,
+        initial_source: &str,
+        playback_state: Arc<Mutex<PlaybackState>>,
+        sample_rate: Option<u32>,
+        osc_dst_addr: &str,
+    ) -> Result<SourceManager, String> {
+        if !sources.contains_key(initial_source) {
+            return Err(format!("Unknown audio source \"{initial_source}\""));
+        }
+        for (name, source) in &sources {
+            for member in source.mix.iter().flatten() {
+                match sources.get(member) {
+                    None => {
+                        return Err(format!(
+                            "Audio source \"{name}\" mixes unknown source \"{member}\""
+                        ))
+                    }
+                    Some(member_source) if member_source.mix.is_some() => {
+                        return Err(format!(
+                            "Audio source \"{name}\" must not mix another mix \"{member}\""
+                        ))
+                    }
+                    Some(_) => (),
+                }
+            }
+        }
+
+        {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.source_names = sources.keys().cloned().collect();
+            playback_state.active_source = initial_source.to_string();
+        }
+
+        Ok(SourceManager {
+            sources,
+            playback_state,
+            sample_rate,
+            osc_dst_addr: osc_dst_addr.to_string(),
+        })
+    }
+
+    /// Returns when shutting down, or when the source ended and there is
+    /// nothing to switch to.
+    pub fn run(&mut self) -> Result<(), String> {
+        loop {
+            let name = self.playback_state.lock().unwrap().active_source.clone();
+            log::info!("Starting audio source \"{name}\"");
+            match self.create(&name) {
+                // The source returns on shutdown, at its end, or when asked to switch
+                Ok(mut source) => source.run(),
+                Err(err) if self.sources.len() == 1 => return Err(err),
+                Err(err) => log::error!("Cannot set up audio source \"{name}\": {err}"),
+            }
+
+            if self.sources.len() == 1 || !self.wait_for_switch() {
+                return Ok(());
+            }
+        }
+    }
+
+    fn create(&self, name: &str) -> Result<Box<dyn AudioSource>, String> {
+        let source = &self.sources[name];
+        let mix = match source.mix.as_ref() {
+            Some(mix) => mix,
+            None => {
+                return source.create(
+                    Arc::clone(&self.playback_state),
+                    self.sample_rate,
+                    &self.osc_dst_addr,
+                )
+            }
+        };
+
+        let members = mix
+            .iter()
+            .map(|member| (member.clone(), self.sources[member].clone()))
+            .collect();
+        Ok(Box::new(MixInput::new(
+            Arc::clone(&self.playback_state),
+            members,
+            self.sample_rate,
+            self.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE),
+            &self.osc_dst_addr,
+        )?))
+    }
+
+    /// Returns false on shutdown
+    fn wait_for_switch(&self) -> bool {
+        loop {
+            {
+                let mut playback_state = self.playback_state.lock().unwrap();
+                if playback_state.shutdown {
+                    return false;
+                }
+                if let Some(name) = playback_state.requested_source.take() {
+                    playback_state.active_source = name;
+                    return true;
+                }
+            }
+
+            thread::sleep(Duration::from_millis(100));
+        }
+    }
+}
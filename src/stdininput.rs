@@ -0,0 +1,119 @@
+use std::io::{self, ErrorKind, Read};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::audiosource::AudioSource;
+use crate::playbackstate::PlaybackState;
+use crate::ringbuffer::SampleRing;
+use crate::sampleconv::{self, PcmFormat, Resampler};
+
+// How often to check for a stop request while stdin is quiet
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reads interleaved raw PCM from stdin, e.g. piped from
+/// `ffmpeg -re -i track.mp3 -f s16le -`
+pub struct StdinInput {
+    playback_state: Arc<Mutex<PlaybackState>>,
+    ring: Arc<SampleRing>,
+    format: PcmFormat,
+    resamplers: Vec<Resampler>,
+    block_len: usize,
+    samples: Vec<f32>,
+}
+
+impl StdinInput {
+    pub fn new(
+        playback_state: Arc<Mutex<PlaybackState>>,
+        format: PcmFormat,
+        input_rate: u32,
+        channels: u16,
+        sample_rate: Option<u32>,
+    ) -> Self {
+        // Analyze at the input rate unless a different one was requested
+        let analysis_rate = sample_rate.unwrap_or(input_rate);
+        let ring = {
+            let mut playback_state = playback_state.lock().unwrap();
+            playback_state.sample_rate = analysis_rate;
+            playback_state.open_ring(channels as usize)
+        };
+
+        // Small blocks keep the latency low, the analysis windows are
+        // assembled from them anyway
+        let frame_count = 256;
+        let block_len = frame_count * channels as usize * format.sample_size();
+
+        StdinInput {
+            playback_state,
+            ring,
+            format,
+            resamplers: (0..channels)
+                .map(|_| Resampler::new(input_rate, analysis_rate))
+                .collect(),
+            block_len,
+            samples: Vec::with_capacity(frame_count * channels as usize),
+        }
+    }
+
+    /// Reads blocks on a separate thread, a quiet pipe would block `run` from
+    /// ever noticing a stop request. The thread isn't joined: it ends after
+    /// the next block or at the end of input, whichever comes first.
+    fn read_blocks(&self) -> Receiver<io::Result<Vec<u8>>> {
+        let (sender, receiver) = mpsc::channel();
+        let block_len = self.block_len;
+        let res = thread::Builder::new()
+            .name("Stdin reader".to_string())
+            .spawn(move || {
+                // Holding the lock keeps a reader of the next StdinInput
+                // waiting until this one is gone
+                let stdin = io::stdin();
+                let mut reader = stdin.lock();
+                loop {
+                    let mut bytes = vec![0u8; block_len];
+                    let res = reader.read_exact(&mut bytes).map(|_| bytes);
+                    let failed = res.is_err();
+                    // Fails once the input stopped
+                    if sender.send(res).is_err() || failed {
+                        break;
+                    }
+                }
+            });
+        if let Err(err) = res {
+            log::error!("Failed to create thread: {err}");
+        }
+
+        receiver
+    }
+}
+
+impl AudioSource for StdinInput {
+    fn run(&mut self) {
+        let input = self.read_blocks();
+
+        loop {
+            if self.playback_state.lock().unwrap().should_stop() {
+                break;
+            }
+
+            let bytes = match input.recv_timeout(POLL_INTERVAL) {
+                Ok(Ok(bytes)) => bytes,
+                Ok(Err(err)) => {
+                    if err.kind() == ErrorKind::UnexpectedEof {
+                        log::info!("End of input on stdin");
+                    } else {
+                        log::error!("Cannot read from stdin: {err}");
+                    }
+                    break;
+                }
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            self.samples.clear();
+            self.format.decode(&bytes, &mut self.samples);
+            let blocks = sampleconv::deinterleave_resampled(&self.samples, &mut self.resamplers);
+            self.ring.push(&blocks);
+        }
+    }
+}
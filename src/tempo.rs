@@ -0,0 +1,243 @@
+use std::collections::VecDeque;
+
+pub const BEATS_PER_BAR: u64 = 4;
+
+/// Seconds of onset strength the tempo is estimated from
+const HISTORY_DURATION: f32 = 6.0;
+/// Shortest history to estimate a tempo from
+const MIN_HISTORY_DURATION: f32 = 3.0;
+/// Seconds between two estimates
+const UPDATE_INTERVAL: f32 = 0.5;
+const MIN_BPM: f32 = 60.0;
+const MAX_BPM: f32 = 200.0;
+/// Tempo most music is played at. Favors it over its multiples and fractions,
+/// which correlate almost as well.
+const PREFERRED_BPM: f32 = 120.0;
+/// Correlation at the beat period relative to the overall energy, below which
+/// there is no discernible beat
+const MIN_CONFIDENCE: f32 = 0.1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tempo {
+    pub bpm: f32,
+    /// Progress through the current beat in [0; 1[
+    pub phase: f32,
+    /// Beats counted since the tempo was first found
+    pub beat: u64,
+    /// In ]0; 1], how clearly the beat stands out
+    pub confidence: f32,
+}
+
+impl Tempo {
+    /// Position of the current beat within its bar, starting at 0. Bars start
+    /// wherever counting started, not necessarily on the downbeat.
+    pub fn beat_in_bar(&self) -> u64 {
+        self.beat % BEATS_PER_BAR
+    }
+}
+
+struct Estimate {
+    /// Beat period in seconds
+    period: f64,
+    /// Time of the beat numbered `anchor_beat`
+    anchor: f64,
+    anchor_beat: u64,
+    confidence: f32,
+}
+
+/// Estimates the tempo by autocorrelating the recent onset strength, and the
+/// beat phase by aligning a comb of beats at that tempo with it.
+pub struct TempoTracker {
+    spectrum_rate: f32,
+    history: VecDeque<f32>,
+    history_len: usize,
+    since_estimate: usize,
+    last_time: f64,
+    estimate: Option<Estimate>,
+    // Kept to not allocate for every estimate
+    centered: Vec<f32>,
+    onsets: Vec<f32>,
+    correlations: Vec<f32>,
+}
+
+impl TempoTracker {
+    /// `spectrum_rate` is the number of onset strengths passed to `process`
+    /// per second
+    pub fn new(spectrum_rate: f32) -> TempoTracker {
+        let history_len = (spectrum_rate * HISTORY_DURATION).round() as usize;
+        TempoTracker {
+            spectrum_rate,
+            history: VecDeque::with_capacity(history_len),
+            history_len,
+            since_estimate: 0,
+            last_time: 0.0,
+            estimate: None,
+            centered: Vec::with_capacity(history_len),
+            onsets: Vec::with_capacity(history_len),
+            correlations: vec![],
+        }
+    }
+
+    /// Takes the onset strength of the spectrum analyzed up to `time` seconds
+    pub fn process(&mut self, onset_strength: f32, time: f64) {
+        if self.history.len() == self.history_len {
+            self.history.pop_front();
+        }
+        self.history.push_back(onset_strength);
+        self.last_time = time;
+
+        self.since_estimate += 1;
+        let min_len = (self.spectrum_rate * MIN_HISTORY_DURATION) as usize;
+        if self.since_estimate as f32 >= self.spectrum_rate * UPDATE_INTERVAL
+            && self.history.len() >= min_len
+        {
+            self.since_estimate = 0;
+            self.estimate();
+        }
+    }
+
+    /// The tempo and where `time` falls within the beats, if there is a beat
+    pub fn tempo(&self, time: f64) -> Option<Tempo> {
+        let estimate = self.estimate.as_ref()?;
+        let beats = ((time - estimate.anchor) / estimate.period).max(0.0);
+        Some(Tempo {
+            bpm: (60.0 / estimate.period) as f32,
+            phase: beats.fract() as f32,
+            beat: estimate.anchor_beat + beats as u64,
+            confidence: estimate.confidence,
+        })
+    }
+
+    fn estimate(&mut self) {
+        let mean = self.history.iter().sum::<f32>() / self.history.len() as f32;
+        let centered = &mut self.centered;
+        centered.clear();
+        centered.extend(self.history.iter().map(|v| v - mean));
+        // Onsets are only a spectrum or two wide. Widening them lets periods
+        // between two lags correlate as well as whole ones.
+        let onsets = &mut self.onsets;
+        onsets.clear();
+        onsets.extend((0..centered.len()).map(|i| {
+            let at = |j: usize| centered.get(j).copied().unwrap_or(0.0);
+            0.25 * at(i.wrapping_sub(1)) + 0.5 * at(i) + 0.25 * at(i + 1)
+        }));
+
+        let min_lag = (60.0 * self.spectrum_rate / MAX_BPM).floor() as usize;
+        let max_lag = (60.0 * self.spectrum_rate / MIN_BPM).ceil() as usize;
+        let energy = autocorrelation(onsets, 0);
+        if energy <= 0.0 || max_lag + 1 >= onsets.len() || min_lag < 2 {
+            self.estimate = None;
+            return;
+        }
+
+        let correlations = &mut self.correlations;
+        correlations.clear();
+        correlations.extend((min_lag - 1..=max_lag + 1).map(|lag| autocorrelation(onsets, lag)));
+        let weight = |lag: usize| {
+            let bpm = 60.0 * self.spectrum_rate / lag as f32;
+            (-0.5 * (bpm / PREFERRED_BPM).log2().powi(2)).exp()
+        };
+        let best = (1..correlations.len() - 1)
+            .max_by(|a, b| {
+                let score = |i: usize| correlations[i] * weight(min_lag - 1 + i);
+                score(*a).total_cmp(&score(*b))
+            })
+            .unwrap();
+
+        let confidence = correlations[best] / energy;
+        if confidence < MIN_CONFIDENCE {
+            self.estimate = None;
+            return;
+        }
+
+        // Parabolic interpolation between the neighboring lags
+        let (before, peak, after) = (
+            correlations[best - 1],
+            correlations[best],
+            correlations[best + 1],
+        );
+        let curvature = before - 2.0 * peak + after;
+        let offset = if curvature < 0.0 {
+            (0.5 * (before - after) / curvature).clamp(-0.5, 0.5)
+        } else {
+            0.0
+        };
+        let period = (min_lag - 1 + best) as f32 + offset;
+
+        let last_beat =
+            self.last_time - beat_offset(onsets, period) as f64 / self.spectrum_rate as f64;
+        let anchor_beat = match &self.estimate {
+            Some(previous) => {
+                let elapsed = ((last_beat - previous.anchor) / previous.period).round();
+                previous.anchor_beat + elapsed.max(0.0) as u64
+            }
+            None => 0,
+        };
+
+        self.estimate = Some(Estimate {
+            period: period as f64 / self.spectrum_rate as f64,
+            anchor: last_beat,
+            anchor_beat,
+            confidence: confidence.min(1.0),
+        });
+    }
+}
+
+fn autocorrelation(values: &[f32], lag: usize) -> f32 {
+    let products: f32 = values[lag..].iter().zip(values).map(|(a, b)| a * b).sum();
+    products / (values.len() - lag) as f32
+}
+
+/// How many values before the last one the most recent beat happened, found
+/// by trying every offset of a comb of beats `period` values apart
+fn beat_offset(onsets: &[f32], period: f32) -> usize {
+    let score = |offset: usize| {
+        let mut sum = 0.0;
+        let mut position = offset as f32;
+        while (position.round() as usize) < onsets.len() {
+            sum += onsets[onsets.len() - 1 - position.round() as usize];
+            position += period;
+        }
+        sum
+    };
+
+    (0..period.ceil() as usize)
+        .max_by(|a, b| score(*a).total_cmp(&score(*b)))
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::generator::testing::{self, HOP_RATE, SAMPLE_RATE};
+    use crate::generator::Signal;
+    use crate::onsets::OnsetDetector;
+
+    /// Tracks 8 s of the signal, returns the tempo at the end
+    fn track(signal: Signal) -> Option<Tempo> {
+        let analyzed = testing::analyze(signal, 0.5, SAMPLE_RATE as usize * 8);
+        let mut onsets = OnsetDetector::new(HOP_RATE);
+        let mut tracker = TempoTracker::new(HOP_RATE);
+        for (time, spectrum) in &analyzed.spectra {
+            onsets.process(spectrum, *time);
+            tracker.process(onsets.onset_strength(), *time);
+        }
+        tracker.tempo(8.0)
+    }
+
+    #[test]
+    fn follows_click_tracks() {
+        for bpm in [95.0, 120.0, 150.0] {
+            let tempo = track(Signal::Click { bpm, freq: 80.0 }).unwrap();
+            assert!((tempo.bpm - bpm).abs() < 1.5, "{bpm}: {tempo:?}");
+            // Clicks start at 0 s and show up in the window shortly after
+            let expected_phase = (8.0 * bpm / 60.0).fract();
+            let error = (tempo.phase - expected_phase + 1.5).fract() - 0.5;
+            assert!(error.abs() < 0.1, "{bpm}: {tempo:?}");
+            assert!(tempo.beat >= 6, "{bpm}: {tempo:?}");
+        }
+
+        assert_eq!(track(Signal::Sine { freq: 80.0 }), None);
+        assert_eq!(track(Signal::Silence), None);
+    }
+}
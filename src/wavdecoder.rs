@@ -0,0 +1,236 @@
+use crate::audiofile::DecodedAudio;
+
+const FORMAT_PCM: u16 = 0x0001;
+const FORMAT_IEEE_FLOAT: u16 = 0x0003;
+const FORMAT_EXTENSIBLE: u16 = 0xFFFE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SampleFormat {
+    U8,
+    I16,
+    I24,
+    I32,
+    F32,
+}
+
+struct FormatChunk {
+    format: SampleFormat,
+    channels: u16,
+    sample_rate: u32,
+    block_align: usize,
+}
+
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([bytes[offset], bytes[offset + 1]])
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes([
+        bytes[offset],
+        bytes[offset + 1],
+        bytes[offset + 2],
+        bytes[offset + 3],
+    ])
+}
+
+pub fn u8_to_f32(v: u8) -> f32 {
+    (v as f32 - 128.0) / 128.0
+}
+
+pub fn i16_to_f32(v: i16) -> f32 {
+    v as f32 / i16::MAX as f32
+}
+
+pub fn i24_to_f32(v: i32) -> f32 {
+    const I24_MAX: i32 = (1 << 23) - 1;
+    v as f32 / I24_MAX as f32
+}
+
+pub fn i32_to_f32(v: i32) -> f32 {
+    v as f32 / i32::MAX as f32
+}
+
+fn decode_sample(format: SampleFormat, b: &[u8]) -> f32 {
+    match format {
+        SampleFormat::U8 => u8_to_f32(b[0]),
+        SampleFormat::I16 => i16_to_f32(i16::from_le_bytes([b[0], b[1]])),
+        // Shift into the upper bytes of an i32 and back to sign-extend
+        SampleFormat::I24 => i24_to_f32(i32::from_le_bytes([0, b[0], b[1], b[2]]) >> 8),
+        SampleFormat::I32 => i32_to_f32(i32::from_le_bytes([b[0], b[1], b[2], b[3]])),
+        SampleFormat::F32 => f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+    }
+}
+
+fn parse_format_chunk(chunk: &[u8]) -> Result<FormatChunk, String> {
+    if chunk.len() < 16 {
+        return Err(format!("fmt chunk too short ({} bytes)", chunk.len()));
+    }
+
+    let mut format_tag = read_u16(chunk, 0);
+    let channels = read_u16(chunk, 2);
+    let sample_rate = read_u32(chunk, 4);
+    let block_align = read_u16(chunk, 12) as usize;
+    let bits_per_sample = read_u16(chunk, 14);
+
+    if format_tag == FORMAT_EXTENSIBLE {
+        // The actual format is stored in the first two bytes of the sub-format GUID
+        if chunk.len() < 26 {
+            return Err("WAVE_FORMAT_EXTENSIBLE fmt chunk too short".to_string());
+        }
+        format_tag = read_u16(chunk, 24);
+    }
+
+    let format = match (format_tag, bits_per_sample) {
+        (FORMAT_PCM, 8) => SampleFormat::U8,
+        (FORMAT_PCM, 16) => SampleFormat::I16,
+        (FORMAT_PCM, 24) => SampleFormat::I24,
+        (FORMAT_PCM, 32) => SampleFormat::I32,
+        (FORMAT_IEEE_FLOAT, 32) => SampleFormat::F32,
+        _ => {
+            return Err(format!(
+                "Unsupported sample format: tag {format_tag:#06x}, {bits_per_sample} bit"
+            ))
+        }
+    };
+
+    if channels == 0 || sample_rate == 0 {
+        return Err(format!(
+            "Invalid format: {channels} channels at {sample_rate} Hz"
+        ));
+    }
+
+    let min_block_align = channels as usize * (bits_per_sample as usize / 8);
+    if block_align < min_block_align {
+        return Err(format!(
+            "Block alignment {block_align} too small for {channels} channels at {bits_per_sample} bit"
+        ));
+    }
+
+    Ok(FormatChunk {
+        format,
+        channels,
+        sample_rate,
+        block_align,
+    })
+}
+
+/// Decodes a RIFF/WAVE file into interleaved f32 samples in [-1; 1].
+pub fn decode_wav(bytes: &[u8]) -> Result<DecodedAudio, String> {
+    if bytes.len() < 12 || &bytes[0..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err("Not a RIFF/WAVE file".to_string());
+    }
+
+    let mut format_chunk = None;
+    let mut data_chunk = None;
+    let mut offset = 12;
+    while offset + 8 <= bytes.len() {
+        let chunk_id = &bytes[offset..offset + 4];
+        let chunk_size = read_u32(bytes, offset + 4) as usize;
+        let chunk_start = offset + 8;
+        // Streaming writers leave the size unset, so don't trust it blindly
+        let chunk_end = chunk_start.saturating_add(chunk_size).min(bytes.len());
+
+        match chunk_id {
+            b"fmt " => format_chunk = Some(parse_format_chunk(&bytes[chunk_start..chunk_end])?),
+            b"data" => data_chunk = Some(&bytes[chunk_start..chunk_end]),
+            _ => {}
+        }
+
+        // Chunks are padded to an even size
+        offset = chunk_end + (chunk_size & 1);
+    }
+
+    let format_chunk = match format_chunk {
+        Some(format_chunk) => format_chunk,
+        None => return Err("Missing fmt chunk".to_string()),
+    };
+    let data = match data_chunk {
+        Some(data) => data,
+        None => return Err("Missing data chunk".to_string()),
+    };
+
+    let channels = format_chunk.channels as usize;
+    let sample_size = format_chunk.block_align / channels;
+    let mut samples = Vec::with_capacity(data.len() / sample_size);
+    for frame in data.chunks_exact(format_chunk.block_align) {
+        for channel in 0..channels {
+            let sample_start = channel * sample_size;
+            samples.push(decode_sample(
+                format_chunk.format,
+                &frame[sample_start..sample_start + sample_size],
+            ));
+        }
+    }
+
+    Ok(DecodedAudio {
+        sample_rate: format_chunk.sample_rate,
+        channels: format_chunk.channels,
+        samples,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_wav(
+        format_tag: u16,
+        channels: u16,
+        sample_rate: u32,
+        bits: u16,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let block_align = channels * bits / 8;
+        let mut wav = Vec::new();
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&format_tag.to_le_bytes());
+        wav.extend_from_slice(&channels.to_le_bytes());
+        wav.extend_from_slice(&sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&bits.to_le_bytes());
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        wav.extend_from_slice(data);
+        wav
+    }
+
+    #[test]
+    fn decodes_stereo_24_bit() {
+        // Frame 0: L = max, R = min; frame 1: L = 0, R = -1
+        let data = [
+            0xFF, 0xFF, 0x7F, 0x01, 0x00, 0x80, 0x00, 0x00, 0x00, 0xFF, 0xFF, 0xFF,
+        ];
+        let audio = decode_wav(&make_wav(FORMAT_PCM, 2, 48000, 24, &data)).unwrap();
+
+        assert_eq!(audio.sample_rate, 48000);
+        assert_eq!(audio.channels, 2);
+        assert_eq!(audio.samples.len(), 4);
+        assert_eq!(audio.samples[0], 1.0);
+        assert!((audio.samples[1] + 1.0).abs() < 1e-6);
+        assert_eq!(audio.samples[2], 0.0);
+        assert!(audio.samples[3] < 0.0 && audio.samples[3] > -0.001);
+    }
+
+    #[test]
+    fn decodes_float_and_unsigned_8_bit() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&0.5f32.to_le_bytes());
+        data.extend_from_slice(&(-0.25f32).to_le_bytes());
+        let audio = decode_wav(&make_wav(FORMAT_IEEE_FLOAT, 1, 22050, 32, &data)).unwrap();
+        assert_eq!(audio.samples, vec![0.5, -0.25]);
+
+        let audio = decode_wav(&make_wav(FORMAT_PCM, 1, 8000, 8, &[128, 0])).unwrap();
+        assert_eq!(audio.samples, vec![0.0, -1.0]);
+    }
+
+    #[test]
+    fn rejects_unsupported_formats() {
+        assert!(decode_wav(&make_wav(FORMAT_PCM, 1, 44100, 12, &[0, 0])).is_err());
+        assert!(decode_wav(b"RIFX\0\0\0\0WAVE").is_err());
+    }
+}